@@ -2,25 +2,40 @@ import io
 import json
 import logging
 import os
+import random
 import re
 import signal
 import secrets
+import shlex
+import shutil
 import sys
-from datetime import datetime, timezone
+import threading
+import time
+from datetime import datetime, timedelta, timezone
 from enum import Enum
 from pathlib import Path
+from urllib.parse import urljoin, urlparse
+from zoneinfo import ZoneInfo
 
-import psycopg2
 import toml
-from PIL import Image
+from PIL import Image, ImageOps
+from psycopg2.extensions import ISOLATION_LEVEL_SERIALIZABLE
 from redis import Redis
-from telegram import Bot
+from telegram import Bot, Update
 from telegram.constants import PARSEMODE_MARKDOWN_V2
-from telegram.error import BadRequest
-from telegram.ext import CommandHandler, MessageHandler, PollHandler, Updater
+from telegram.error import BadRequest, RetryAfter, TelegramError, Unauthorized
+from telegram.ext import (
+    CommandHandler,
+    DispatcherHandlerStop,
+    MessageHandler,
+    PollHandler,
+    TypeHandler,
+    Updater,
+)
 from telegram.ext.filters import Filters
 from telegram.utils.request import Request
 
+from database import ThreadConnection, in_transaction
 from utils import (
     apos,
     duration,
@@ -29,9 +44,18 @@ from utils import (
     enum_values,
     ext,
     find_enum_by_value,
+    generate_seeding,
     markdown_escape,
+    normalize_text,
+    normalize_username,
     now,
+    order_submissions,
     plural,
+    poll_options_error,
+    presentation_order,
+    round_index,
+    round_name,
+    round_starts,
 )
 
 
@@ -39,11 +63,111 @@ project_path = Path(os.getenv("GIFDOME_DIR", Path(sys.path[0]).parent))
 config = toml.load(project_path / "config.toml")
 
 DEBUG = config["debug"]["enabled"]
+TESTING = config["debug"].get("testing", False)
+
+if (rng_seed := config["debug"].get("rng_seed")) is not None:
+    rng = random.Random(rng_seed)
+else:
+    rng = secrets.SystemRandom()
+
+
+def parse_clock_time(text):
+    hour, minute = (int(part) for part in text.split(":"))
+    if not (0 <= hour < 24 and 0 <= minute < 60):
+        raise ValueError(f"Invalid time {text!r}")
+    return hour, minute
+
+
+# Listed from the round of 256 to the final
+default_round_durations = [1800, 1800, 3600, 7200, 10800, 21600, 43200, 86400]
+# No poll stays open longer than this, whatever the config or admins ask for
+max_duration_ceiling = 7 * 24 * 3600
+
 
 apos = "\u2019"
 emoji_a = "\U0001F170\uFE0F"
 emoji_b = "\U0001F171\uFE0F"
 
+def load_poll_texts(config):
+    question = config.get("poll", {}).get("question", "Which shall win?")
+    options = [
+        config.get("poll", {}).get("option_a", emoji_a),
+        config.get("poll", {}).get("option_b", emoji_b),
+    ]
+    if not 1 <= len(question) <= 300:
+        raise ValueError("poll.question must be 1-300 characters")
+    if (error := poll_options_error(options)) is not None:
+        raise ValueError(f"Invalid poll.option_a or poll.option_b: {error}")
+    if config.get("poll", {}).get("preview", "versus") not in ["versus", "separate"]:
+        raise ValueError("poll.preview must be \"versus\" or \"separate\"")
+    return question, options
+
+
+def per_round_min_votes(config):
+    # Every round uses the flat min_votes unless listed per round
+    return config.get("round_min_votes", [config["min_votes"]] * len(round_starts))
+
+
+def validate_config(config):
+    # Checked at startup and before a reload is applied
+    if config.get("update_mode", "webhook") not in ["webhook", "polling"]:
+        raise ValueError(f"Invalid update_mode {config['update_mode']!r} in config")
+
+    if config.get("max_match_duration", 0) > max_duration_ceiling:
+        raise ValueError("max_match_duration must be at most a week")
+
+    round_durations = config.get("round_durations", default_round_durations)
+    if len(round_durations) != len(round_starts):
+        raise ValueError(f"round_durations must list {len(round_starts)} durations, from the first round to the final")
+    if not all(isinstance(seconds, int) and seconds > 0 for seconds in round_durations):
+        raise ValueError("round_durations must be positive numbers of seconds")
+    if any(seconds > max_duration_ceiling for seconds in round_durations):
+        raise ValueError("round_durations must be at most a week each")
+
+    # The default always has the right length, so this only fails for a listed round_min_votes
+    min_votes = per_round_min_votes(config)
+    if len(min_votes) != len(round_starts):
+        raise ValueError(f"round_min_votes must list {len(round_starts)} vote counts, from the first round to the final")
+    if not all(isinstance(votes, int) and votes >= 0 for votes in min_votes):
+        raise ValueError("min_votes and round_min_votes must be non-negative numbers of votes")
+
+    min_margin = config.get("min_margin", 0)
+    if isinstance(min_margin, str) and min_margin.endswith("%"):
+        try:
+            percentage = float(min_margin[:-1])
+        except ValueError:
+            percentage = None
+        if percentage is None or not 0 <= percentage <= 100:
+            raise ValueError("min_margin must be a percentage from \"0%\" to \"100%\"")
+    elif isinstance(min_margin, bool) or not isinstance(min_margin, int) or min_margin < 0:
+        raise ValueError("min_margin must be a non-negative number of votes or a percentage like \"10%\"")
+
+    base_url = config.get("public_base_url", "https://gifdome.dipo.rocks/")
+    parsed_base_url = urlparse(base_url)
+    if parsed_base_url.scheme not in ["http", "https"] or not parsed_base_url.netloc:
+        raise ValueError(f"Invalid public_base_url {base_url!r} in config")
+
+    if (quiet_hours := config.get("quiet_hours", {})).get("enabled", False):
+        ZoneInfo(quiet_hours.get("timezone", "UTC"))
+        parse_clock_time(quiet_hours["start"])
+        parse_clock_time(quiet_hours["end"])
+
+    load_poll_texts(config)
+
+
+validate_config(config)
+update_mode = config.get("update_mode", "webhook")
+public_base_url = config.get("public_base_url", "https://gifdome.dipo.rocks/")
+poll_question, poll_options = load_poll_texts(config)
+
+for dir_path in [project_path / "gifs", Path(config["log_file"]).parent]:
+    if not dir_path.is_dir():
+        if not config.get("create_dirs", False):
+            raise ValueError(f"Directory {dir_path} does not exist")
+        dir_path.mkdir(parents=True)
+    if not os.access(dir_path, os.W_OK):
+        raise ValueError(f"Directory {dir_path} is not writable")
+
 logging.basicConfig(filename=config["log_file"], level=logging.INFO)
 
 
@@ -61,16 +185,85 @@ def _find_enum_by_value(enum, value):
     return None
 
 
-bot = Bot(
+class RateLimiter:
+    # Hands out send slots in order, at most per_second overall and one per
+    # chat_interval seconds in each chat; callers sleep until their slot
+    def __init__(self, per_second, chat_interval):
+        self.lock = threading.Lock()
+        self.interval = 1 / per_second
+        self.chat_interval = chat_interval
+        self.next_send = 0
+        self.next_chat_send = {}
+
+    def wait(self, chat_id):
+        with self.lock:
+            current = time.monotonic()
+            slot = max(current, self.next_send, self.next_chat_send.get(chat_id, 0))
+            self.next_send = slot + self.interval
+            self.next_chat_send[chat_id] = slot + self.chat_interval
+        if slot > current:
+            time.sleep(slot - current)
+
+
+rate_limit_config = config.get("rate_limit", {})
+send_limiter = RateLimiter(
+    rate_limit_config.get("per_second", 30),
+    rate_limit_config.get("chat_interval", 1),
+)
+
+
+def rate_limited(method):
+    def wrapper(self, *args, **kwargs):
+        # Sends happen after committing; if one slips into a transaction, don't
+        # hold the transaction open while waiting
+        transaction_open = db.transaction_open()
+        if transaction_open:
+            logging.warning(f"{method.__name__} called in a database transaction, not rate limiting it")
+        elif rate_limit_config.get("enabled", True):
+            send_limiter.wait(kwargs.get("chat_id", args[0] if args else None))
+        try:
+            return method(self, *args, **kwargs)
+        except RetryAfter as e:
+            if transaction_open:
+                raise e
+            logging.warning(f"Rate limited by Telegram, retrying {method.__name__} in {e.retry_after} seconds")
+            time.sleep(e.retry_after)
+            return method(self, *args, **kwargs)
+    return wrapper
+
+
+class LimitedBot(Bot):
+    pass
+
+
+for method_name in [
+    "send_animation",
+    "send_message",
+    "send_photo",
+    "send_poll",
+    "send_sticker",
+]:
+    setattr(LimitedBot, method_name, rate_limited(getattr(Bot, method_name)))
+
+
+bot = LimitedBot(
     token=config["api_token"],
+    base_url=config.get("api_base_url", "https://api.telegram.org/bot"),
+    base_file_url=config.get("api_base_file_url", "https://api.telegram.org/file/bot"),
     request=Request(con_pool_size=16, read_timeout=15),
 )
 
+# Operator alerts can come from a separate bot so they don't mix with the group bot's chats
+if "admin_api_token" in config:
+    admin_bot = LimitedBot(token=config["admin_api_token"])
+else:
+    admin_bot = bot
+
 updater = Updater(bot=bot)
 dispatcher = updater.dispatcher
 
 db_name = config.get("db_name", "gifdome")
-db = psycopg2.connect(f"dbname={db_name}")
+db = ThreadConnection(f"dbname={db_name}")
 
 with db:
     with open(project_path / "schema.sql") as schema:
@@ -81,6 +274,73 @@ with db:
 redis = Redis(unix_socket_path=config["redis_socket"], db=config["redis_db"])
 
 
+def notify_admins(text):
+    for chat_id in config.get("admin_chat_ids", []):
+        try:
+            admin_bot.send_message(chat_id=chat_id, text=text)
+        except Exception as e:
+            logging.exception(e)
+
+
+def enqueue_message(pipe, method, **kwargs):
+    pipe.rpush("outbox", json.dumps({"method": method, "kwargs": kwargs}))
+
+
+outbox_lock = threading.Lock()
+
+
+def drain_outbox(context=None):
+    with outbox_lock:
+        while (item := redis.lindex("outbox", 0)) is not None:
+            message = json.loads(item)
+            try:
+                sent = getattr(bot, message["method"])(**message["kwargs"])
+                # Lets the next poll reply to the announcement before it
+                redis.set("last_outbox_message", sent.message_id)
+            except BadRequest as e:
+                # Retrying won't help, e.g. the chat is gone
+                logging.warning(f"Dropping outbox message {message}: {e.message}")
+            except TelegramError as e:
+                logging.warning(f"Could not send outbox message, retrying later: {e.message}")
+                return
+            redis.lpop("outbox")
+
+
+updater.job_queue.run_repeating(drain_outbox, interval=config.get("outbox_interval", 10))
+
+
+def pin_message(chat_id, message_id, **kwargs):
+    if TESTING:
+        logging.info(f"Testing mode: not pinning message {message_id} in {chat_id}")
+        return
+    bot.pin_chat_message(chat_id=chat_id, message_id=message_id, **kwargs)
+
+
+def unpin_message(chat_id, message_id):
+    if TESTING:
+        logging.info(f"Testing mode: not unpinning message {message_id} in {chat_id}")
+        return
+    try:
+        bot.unpin_chat_message(chat_id=chat_id, message_id=message_id)
+    except BadRequest as e:
+        # The message may have been deleted or unpinned by hand already
+        logging.warning(f"Could not unpin message {message_id}: {e.message}")
+
+
+def current_poll_options():
+    if (options := redis.get("poll_options")) is not None:
+        return json.loads(options)
+    return poll_options
+
+
+def current_poll_labels():
+    # The options the current poll was actually sent with, which differ from
+    # current_poll_options() if Telegram rejected those
+    if (labels := redis.get("current_poll_labels")) is not None:
+        return json.loads(labels)
+    return current_poll_options()
+
+
 def redis_get_int(key):
     try:
         return int(redis.get(key))
@@ -88,10 +348,26 @@ def redis_get_int(key):
         return None
 
 
-def reset():
-    with db:
-        with db.cursor() as cur:
-            cur.execute('DELETE FROM "submissions"')
+def save_previous_settings():
+    if (group_id := redis_get_int("group_id")) is None:
+        return
+    settings = {
+        "group_id": group_id,
+        "poll_options": current_poll_options(),
+        "mode": (redis.get("mode") or b"tournament").decode(),
+    }
+    redis.set("previous_settings", json.dumps(settings))
+
+
+def reset(*, delete_submissions=True):
+    save_previous_settings()
+    # However the GIFdome ends, its group stops sending in submissions
+    if (group_id := redis_get_int("group_id")) is not None:
+        gif_chat_filter.remove_chat_ids(group_id)
+    if delete_submissions:
+        with db:
+            with db.cursor() as cur:
+                cur.execute('DELETE FROM "submissions"')
     redis.set("state", State.NOT_STARTED.value)
     for key in [
         "group_id",
@@ -101,10 +377,33 @@ def reset():
         "current_poll",
         "current_poll_start",
         "current_voter_count",
+        "current_votes",
+        "current_stickers_caption",
+        "current_poll_labels",
+        "current_preview",
+        "current_order_swapped",
+        "last_outbox_message",
+        "time_cap_attempt",
+        "current_caption_votes",
         "matches",
+        "submission_status_message",
+        "submission_status_dirty",
+        "poll_options",
+        "mode",
+        "next_poll_at",
+        "ended_at",
+        "submissions_started_at",
+        "stale_nudged_at",
+        "title",
+        "pending_seeding",
+        "pending_seed_order",
+        "pending_seeding_submissions",
+        "seed_order",
         #"seeding",
     ]:
         redis.delete(key)
+    for key in redis.scan_iter("reports:*"):
+        redis.delete(key)
 
 
 exiting = False
@@ -129,6 +428,61 @@ for signalnum in {signal.SIGINT, signal.SIGTERM}:
     signal.signal(signalnum, exit_handler)
 
 
+# Settings that are read once at startup (connections, handler filters, jobs)
+# and need a restart to change
+restart_config_keys = {
+    "admins",
+    "admin_api_token",
+    "admin_chat_ids",
+    "api_base_file_url",
+    "api_base_url",
+    "api_token",
+    "cleanup",
+    "confirm_mode",
+    "db_name",
+    "debug",
+    "digest_interval",
+    "live_votes",
+    "log_file",
+    "outbox_interval",
+    "public_base_url",
+    "rate_limit",
+    "redis_db",
+    "redis_socket",
+    "stale_submissions",
+    "update_mode",
+    "webhook_port",
+    "webhook_url",
+}
+
+
+def reload_config(signalnum, frame):
+    global poll_question, poll_options
+    try:
+        new_config = toml.load(project_path / "config.toml")
+        validate_config(new_config)
+        new_poll_texts = load_poll_texts(new_config)
+    except Exception as e:
+        logging.warning(f"Not reloading invalid config: {e}")
+        return
+
+    for key in restart_config_keys:
+        if new_config.get(key) != config.get(key):
+            logging.warning(f"Changing {key} requires a restart, ignoring it")
+    for key in set(config) | set(new_config):
+        if key in restart_config_keys:
+            continue
+        if key in new_config:
+            config[key] = new_config[key]
+        else:
+            del config[key]
+    poll_question, poll_options = new_poll_texts
+    logging.info("Reloaded config")
+
+
+signal.signal(signal.SIGHUP, reload_config)
+
+
 def update_chat_description():
     if (group_id := redis_get_int("group_id")) is None:
         return
@@ -138,29 +492,14 @@ def update_chat_description():
         description = "Send your dankest GIFs!"
     elif state == State.VOTING.value:
         match_num = redis_get_int("current_match")
-        if match_num < 128:
-            round_of = "round of 256"
-        elif match_num < 192:
-            round_of = "round of 128"
-        elif match_num < 224:
-            round_of = "round of 64"
-        elif match_num < 240:
-            round_of = "round of 32"
-        elif match_num < 248:
-            round_of = "round of 16"
-        elif match_num < 252:
-            round_of = "quarterfinals"
-        elif match_num < 254:
-            round_of = "semifinals"
-        elif match_num == 254:
-            round_of = "the FINALE"
-        else:
-            round_of = f"wait, that shouldn{apos}t happen"
-        description = f"Vote for the ultimate GIF!\nCurrent vote: {match_num + 1}/255 ({round_of})"
+        description = f"Vote for the ultimate GIF!\nCurrent vote: {match_num + 1}/255 ({round_name(match_num)})"
     elif state == State.ENDED.value:
-        description = "This GIFdome has ended."
+        description = f"This {gifdome_name()} has ended."
     else:
         description = "The GIFdome aims to find the ultimate GIF by process of elimination."
+    if TESTING:
+        logging.info(f"Testing mode: not setting chat description to {description!r}")
+        return
     try:
         bot.set_chat_description(chat_id=group_id, description=description)
     except BadRequest as e:
@@ -168,6 +507,33 @@ def update_chat_description():
             raise e
 
 
+def max_file_size(mime_type):
+    limit = config.get("max_file_size")
+    if isinstance(limit, dict):
+        return limit.get(mime_type, limit.get("default"))
+    return limit
+
+
+def temp_path(file_path):
+    return file_path.with_name(f"{file_path.name}.{secrets.token_hex(8)}.part")
+
+
+def download_file(file, file_path):
+    # Download under a unique name and rename, so concurrent downloads of the
+    # same file can't clobber each other or leave a partial file in place
+    part_path = temp_path(file_path)
+    try:
+        # A local Bot API server returns an absolute path on its own filesystem
+        if config.get("local_api_server", False) and Path(file.file_path).is_absolute():
+            shutil.copyfile(file.file_path, part_path)
+        else:
+            with open(part_path, "wb") as f:
+                file.download(out=f)
+        os.replace(part_path, file_path)
+    finally:
+        part_path.unlink(missing_ok=True)
+
+
 def upsert_gif(gif, user, *, new_transaction=False):
     with db.cursor() as cur:
         cur.execute(
@@ -190,6 +556,10 @@ def upsert_gif(gif, user, *, new_transaction=False):
         if gif_submissions == 0 and user_submissions >= max_:
             return f"You{apos}ve already submitted {max_} GIFs."
 
+        max_size = max_file_size(gif.mime_type)
+        if gif_submissions == 0 and max_size is not None and (gif.file_size or 0) > max_size:
+            return f"This GIF is too big, the limit is {max_size // 1000} kB."
+
         cur.execute(
             """
             INSERT INTO "gifs"(
@@ -222,33 +592,66 @@ def upsert_gif(gif, user, *, new_transaction=False):
                 gif.duration,
             ),
         )
-        if gif.file_name:
+        if gif.file_name and (filename := normalize_text(gif.file_name, 255)):
             cur.execute(
                 """
                 INSERT INTO "gif_filenames"("gif_id", "filename") VALUES (%s, %s)
                     ON CONFLICT ("gif_id", "filename") DO NOTHING
                 """,
-                (gif.file_unique_id, gif.file_name),
+                (gif.file_unique_id, filename),
             )
-        cur.execute(
-            'SELECT "file_id", "mime_type" FROM "gifs" WHERE "id" = %s',
-            (gif.file_unique_id,),
-        )
-        if cur.rowcount != 1:
-            raise Exception(f'Got {cur.rowcount} results from "gifs"')
-        file_id, mime_type = cur.fetchone()
 
     if new_transaction:
         db.commit()
 
-    file_path = project_path / "gifs" / f"{file_id}{ext(mime_type)}"
-    if not file_path.is_file():
-        with open(file_path, "wb") as f:
-            gif.get_file().download(out=f)
-
     return None
 
 
+def is_blocked(cur, gif_id):
+    if config.get("duplicate_detection", True):
+        # Resubmitting a duplicate of a blocked GIF doesn't get around the block
+        cur.execute(
+            """
+            SELECT count(*) FROM "blocked_gifs" WHERE "gif_id" = %s
+                OR "gif_id" IN (SELECT "primary_id" FROM "duplicates" WHERE "duplicate_id" = %s)
+            """,
+            (gif_id, gif_id),
+        )
+    else:
+        cur.execute('SELECT count(*) FROM "blocked_gifs" WHERE "gif_id" = %s', (gif_id,))
+    blocked, = cur.fetchone()
+    return blocked > 0
+
+
+def gif_file_id(cur, gif_id):
+    # Bracket entries, poll previews, winner announcements and /winner all
+    # resolve GIF IDs through this
+    cur.execute('SELECT "file_id" FROM "gifs" WHERE "id" = %s', (gif_id,))
+    if cur.rowcount != 1:
+        return None
+    file_id, = cur.fetchone()
+    return file_id
+
+
+def gif_file_path(cur, gif_id):
+    cur.execute('SELECT "file_id", "mime_type" FROM "gifs" WHERE "id" = %s', (gif_id,))
+    if cur.rowcount != 1:
+        return None
+    file_id, mime_type = cur.fetchone()
+    return project_path / "gifs" / f"{file_id}{ext(mime_type)}"
+
+
+def download_gif(gif, file_path):
+    if file_path.is_file():
+        return
+    try:
+        download_file(gif.get_file(), file_path)
+    except Exception as e:
+        # The submission already counts; the download is tried again whenever
+        # the GIF gets submitted
+        logging.exception(e)
+
+
 def upsert_user(user, *, new_transaction=False):
     with db.cursor() as cur:
         cur.execute(
@@ -256,12 +659,50 @@ def upsert_user(user, *, new_transaction=False):
             INSERT INTO "users"("id", "username") VALUES (%s, %s)
                 ON CONFLICT ("id") DO UPDATE SET "username" = %s
             """,
-            (user.id, user.username, user.username)
+            (user.id, normalize_username(user.username), normalize_username(user.username))
         )
     if new_transaction:
         db.commit()
 
 
+def privacy_command(update, context):
+    user = update.effective_user
+    usage = "Usage: /privacy [name] [on|off]"
+    settings = {"name": "show_name"}
+    if context.args and (
+        len(context.args) != 2 or context.args[0] not in settings or context.args[1] not in ["on", "off"]
+    ):
+        update.effective_message.reply_text(usage)
+        return
+
+    with db:
+        upsert_user(user)
+        with db.cursor() as cur:
+            if context.args:
+                cur.execute(
+                    f'UPDATE "users" SET "{settings[context.args[0]]}" = %s WHERE "id" = %s',
+                    (context.args[1] == "on", user.id),
+                )
+            cur.execute('SELECT "show_name" FROM "users" WHERE "id" = %s', (user.id,))
+            show_name, = cur.fetchone()
+
+    def on_off(value):
+        return "on" if value else "off"
+
+    update.effective_message.reply_text("\n".join([
+        f"Credit me by name in announcements (name): {on_off(show_name)}",
+        usage,
+    ]))
+
+
+privacy_handler = CommandHandler(
+    command="privacy",
+    callback=privacy_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(privacy_handler)
+
+
 def add_submission(message, user, gif, *, new_transaction=False):
     def get_user_submission_count(cur):
         cur.execute(
@@ -286,14 +727,15 @@ def add_submission(message, user, gif, *, new_transaction=False):
         max_ = config["max_submissions"]
 
         gif_id = gif.file_unique_id
-        cur.execute(
-            'SELECT "primary_id" FROM "duplicates" WHERE "duplicate_id" = %s',
-            (gif_id,),
-        )
-        if cur.rowcount > 1:
-            raise Exception(f"Got {cur.rowcount} primary IDs for duplicate ID {gif.file_unique_id}")
-        if cur.rowcount == 1:
-            gif_id, = cur.fetchone()
+        if config.get("duplicate_detection", True):
+            cur.execute(
+                'SELECT "primary_id" FROM "duplicates" WHERE "duplicate_id" = %s',
+                (gif_id,),
+            )
+            if cur.rowcount > 1:
+                raise Exception(f"Got {cur.rowcount} primary IDs for duplicate ID {gif.file_unique_id}")
+            if cur.rowcount == 1:
+                gif_id, = cur.fetchone()
 
         cur.execute(
             'SELECT count(*) FROM "submissions" WHERE "user_id" = %s AND "gif_id" = %s',
@@ -303,12 +745,15 @@ def add_submission(message, user, gif, *, new_transaction=False):
             raise Exception(f"Got {cur.rowcount} rows from counting user's GIF submissions")
         user_gif_submissions, = cur.fetchone()
         if user_gif_submissions != 0:
-            message.reply_text(f"You{apos}ve already submitted this GIF.")
-            return
+            return f"You{apos}ve already submitted this GIF.", None
 
+        description = normalize_text(message.caption, 200) if message.caption else None
         cur.execute(
-            'INSERT INTO "submissions"("user_id", "gif_id", "created") VALUES (%s, %s, %s)',
-            (user.id, gif_id, datetime.now(timezone.utc)),
+            """
+            INSERT INTO "submissions"("user_id", "gif_id", "created", "description", "submitted_gif_id")
+                VALUES (%s, %s, %s, %s, %s)
+            """,
+            (user.id, gif_id, datetime.now(timezone.utc), description or None, gif.file_unique_id),
         )
         if cur.rowcount != 1:
             raise Exception(f"{cur.rowcount} rows inserted into submissions")
@@ -317,41 +762,218 @@ def add_submission(message, user, gif, *, new_transaction=False):
         user_submissions = get_user_submission_count(cur)
         if gif_submissions == 0:
             raise Exception("Zero submission count after inserting submission")
-        elif gif_submissions == 1:
-            message.reply_text(
-                f"Thanks for the new GIF! You have submitted {user_submissions}/{max_} GIFs.",
-            )
-        else:
-            message.reply_text(
-                f"Got it! This GIF has been submitted {gif_submissions} times.",
-            )
 
     if new_transaction:
         db.commit()
 
+    # The caller replies once the transaction has committed; a reaction, if
+    # given, replaces the reply unless it fails
+    if config.get("confirm_mode", "reply") == "digest":
+        redis.set("submission_status_dirty", 1)
+        return None, None
+    if gif_submissions == 1:
+        return (
+            f"Thanks for the new GIF! You have submitted {user_submissions}/{max_} GIFs.",
+            config.get("ack_reaction"),
+        )
+    return f"Got it! This GIF has been submitted {gif_submissions} times.", None
+
+
+def skip_seen_updates(update, context):
+    # Telegram redelivers updates it considers unacknowledged, e.g. after a slow
+    # webhook response; handle each update ID only once
+    if not redis.set(f"update:{update.update_id}", 1, nx=True, ex=24 * 3600):
+        raise DispatcherHandlerStop
+
+
+seen_updates_handler = TypeHandler(type=Update, callback=skip_seen_updates)
+dispatcher.add_handler(seen_updates_handler, group=-2)
+
+
+def check_command_enabled(update, context):
+    message = update.effective_message
+    command, _, username = message.text.split()[0][1:].partition("@")
+    if username and username.lower() != bot.username.lower():
+        return
+
+    commands_config = config.get("commands", {})
+    disabled = set(commands_config.get("disabled", []))
+    disabled.update(commands_config.get("disabled_in_chat", {}).get(str(update.effective_chat.id), []))
+    if command.lower() in disabled:
+        message.reply_text("This command is disabled here.")
+        raise DispatcherHandlerStop
+
+
+command_check_handler = MessageHandler(callback=check_command_enabled, filters=Filters.command)
+dispatcher.add_handler(command_check_handler, group=-1)
+
+
+def update_submission_status(context):
+    if redis.get("state") != State.TAKING_SUBMISSIONS.value:
+        return
+    if not redis.get("submission_status_dirty"):
+        return
+    if (group_id := redis_get_int("group_id")) is None:
+        return
+    redis.delete("submission_status_dirty")
+
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT count(DISTINCT "gif_id"), count(DISTINCT "user_id") FROM "submissions"')
+            gif_count, user_count = cur.fetchone()
+    text = (
+        f"{gif_count} GIF{plural(gif_count)} submitted, "
+        f"{user_count} participant{plural(user_count)}"
+    )
+
+    if (message_id := redis_get_int("submission_status_message")) is not None:
+        try:
+            bot.edit_message_text(chat_id=group_id, message_id=message_id, text=text)
+            return
+        except BadRequest as e:
+            if e.message.startswith("Message is not modified"):
+                return
+            if e.message != "Message to edit not found":
+                raise e
+
+    status_message = bot.send_message(chat_id=group_id, text=text)
+    redis.set("submission_status_message", status_message.message_id)
+
+
+if config.get("confirm_mode", "reply") == "digest":
+    updater.job_queue.run_repeating(
+        update_submission_status,
+        interval=config.get("digest_interval", 60),
+    )
+
+
+def clean_up_gif_files(context):
+    referenced_ids = set()
+    if (seeding := redis.get("seeding")) is not None:
+        referenced_ids.update(json.loads(seeding))
+
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT DISTINCT "gif_id" FROM "submissions"')
+            referenced_ids.update(gif_id for gif_id, in cur)
+            cur.execute('SELECT "id", "file_id", "mime_type" FROM "gifs"')
+            files = {
+                f"{file_id}{ext(mime_type)}": id_
+                for id_, file_id, mime_type in cur
+            }
+
+    cutoff = now() - config["cleanup"]["retention_days"] * 24 * 3600
+    removed = 0
+    for file_path in (project_path / "gifs").iterdir():
+        gif_id = files.get(file_path.name)
+        if gif_id is None or gif_id in referenced_ids:
+            continue
+        if file_path.stat().st_mtime >= cutoff:
+            continue
+        file_path.unlink()
+        removed += 1
+    if removed:
+        logging.info(f"Removed {removed} unreferenced GIF file{plural(removed)}")
+
+
+if config.get("cleanup", {}).get("enabled", False):
+    updater.job_queue.run_repeating(
+        clean_up_gif_files,
+        interval=config["cleanup"].get("interval", 24 * 3600),
+    )
+
+
+def react(message, emoji):
+    # python-telegram-bot 13 predates setMessageReaction, so call the Bot API directly
+    try:
+        bot.request.post(
+            f"{bot.base_url}/setMessageReaction",
+            {
+                "chat_id": message.chat.id,
+                "message_id": message.message_id,
+                "reaction": [{"type": "emoji", "emoji": emoji}],
+            },
+        )
+    except TelegramError as e:
+        logging.warning(f"Could not react to message {message.message_id}: {e.message}")
+        return False
+    return True
+
 
 def gif_message(update, context):
     message = update.message
     if message.reply_to_message:
         return
+    # The chat filter should already keep these out, but a GIF must never be
+    # recorded once submissions are closed
+    if redis.get("state") != State.TAKING_SUBMISSIONS.value:
+        return
 
     gif = message.animation
     if not gif:
         return
     user = message.from_user
-    with db:
-        try:
-            upsert_user(user)
-            reply = upsert_gif(gif, user)
-            if reply is not None:
-                message.reply_text(reply)
-                return
-            add_submission(message, user, gif)
-        except Exception as e:
-            message.reply_text(
-                "Welp! Something went wrong when trying to process your submission."
-            )
-            logging.exception(e)
+
+    forwarded = message.forward_date is not None
+    forwarded_gifs = config.get("forwarded_gifs", "count")
+    if forwarded and forwarded_gifs == "ignore":
+        message.reply_text(f"Forwarded GIFs don{apos}t count, send the GIF yourself.")
+        return
+    if forwarded and forwarded_gifs == "origin":
+        # Credit whoever originally sent the GIF so forwarding it around doesn't add submissions
+        if message.forward_from is None or message.forward_from.is_bot:
+            message.reply_text(f"Can{apos}t tell who originally sent this GIF, send it yourself instead.")
+            return
+        user = message.forward_from
+
+    def process_submission():
+        # Returns the file to download or remove and the response, which are
+        # only acted on after the commit since serialization failures rerun this
+        upsert_user(user)
+        with db.cursor() as cur:
+            cur.execute('SELECT count(*) FROM "banned_users" WHERE "user_id" = %s', (user.id,))
+            banned, = cur.fetchone()
+        if banned:
+            if config.get("banned_submissions", "ignore") == "reply":
+                return None, False, f"You{apos}re not allowed to submit GIFs.", None
+            return None, False, None, None
+        if config.get("require_description", False) and not (message.caption or "").strip():
+            return None, False, "Please send the GIF again with a caption describing it.", None
+        # Checked before the GIF is stored so blocked GIFs are never downloaded
+        with db.cursor() as cur:
+            if is_blocked(cur, gif.file_unique_id):
+                # The file may have been downloaded before the GIF was blocked
+                file_path = gif_file_path(cur, gif.file_unique_id)
+                return file_path, True, "This GIF has been blocked.", None
+        reply = upsert_gif(gif, user)
+        if reply is not None:
+            return None, False, reply, None
+        with db.cursor() as cur:
+            file_path = gif_file_path(cur, gif.file_unique_id)
+        reply, reaction = add_submission(message, user, gif)
+        return file_path, False, reply, reaction
+
+    try:
+        file_path, blocked, reply, reaction = in_transaction(
+            db,
+            process_submission,
+            isolation_level=ISOLATION_LEVEL_SERIALIZABLE,
+        )
+    except Exception as e:
+        message.reply_text(
+            "Welp! Something went wrong when trying to process your submission."
+        )
+        logging.exception(e)
+        return
+
+    if file_path is not None and blocked:
+        file_path.unlink(missing_ok=True)
+    elif file_path is not None:
+        download_gif(gif, file_path)
+    if reaction is not None and react(message, reaction):
+        return
+    if reply is not None:
+        message.reply_text(reply)
 
 
 gif_chat_filter = Filters.chat()
@@ -362,12 +984,92 @@ gif_handler = MessageHandler(
 dispatcher.add_handler(gif_handler)
 
 
+def reply_target(update, command):
+    message = update.effective_message
+    target = message.reply_to_message and message.reply_to_message.from_user
+    if target is None or target.is_bot:
+        message.reply_text(f"Reply to a message from the user with /{command}.")
+        return None
+    return target
+
+
+def ban_command(update, context):
+    if (target := reply_target(update, "ban")) is None:
+        return
+    with db:
+        upsert_user(target)
+        with db.cursor() as cur:
+            cur.execute(
+                """
+                INSERT INTO "banned_users"("user_id", "created") VALUES (%s, %s)
+                    ON CONFLICT DO NOTHING
+                """,
+                (target.id, datetime.now(timezone.utc)),
+            )
+    update.effective_message.reply_text(f"{target.full_name} can no longer submit GIFs.")
+
+
+ban_handler = CommandHandler(
+    command="ban",
+    callback=ban_command,
+    filters=Filters.chat_type.groups & Filters.user(username=config["admins"]),
+)
+dispatcher.add_handler(ban_handler)
+
+
+def unban_command(update, context):
+    if (target := reply_target(update, "unban")) is None:
+        return
+    with db:
+        with db.cursor() as cur:
+            cur.execute('DELETE FROM "banned_users" WHERE "user_id" = %s', (target.id,))
+    update.effective_message.reply_text(f"{target.full_name} can submit GIFs again.")
+
+
+unban_handler = CommandHandler(
+    command="unban",
+    callback=unban_command,
+    filters=Filters.chat_type.groups & Filters.user(username=config["admins"]),
+)
+dispatcher.add_handler(unban_handler)
+
+
+def late_gif_message(update, context):
+    message = update.message
+    if message.reply_to_message:
+        return
+    if config.get("late_submissions", "ignore") != "reply":
+        return
+    if redis.get("state") != State.VOTING.value or message.chat.id != redis_get_int("group_id"):
+        return
+    message.reply_text(f"Submissions are closed, it{apos}s voting time! This GIF wasn{apos}t counted.")
+
+
+late_gif_handler = MessageHandler(
+    callback=late_gif_message,
+    filters=Filters.animation & Filters.chat_type.groups,
+)
+dispatcher.add_handler(late_gif_handler)
+
+
+def public_url(path=""):
+    return urljoin(public_base_url, path)
+
+
+def public_link():
+    url = public_url()
+    text = markdown_escape(urlparse(url).netloc + urlparse(url).path.rstrip("/"))
+    # Inside the link target only ")" and "\" have to be escaped
+    target = re.sub(r"[)\\]", r"\\\g<0>", url)
+    return f"[{text}]({target})"
+
+
 def send_bracket(chat_id, caption=None, parse_mode=None):
     if not (project_path / "bracket.png").is_file():
         update_bracket_image()
 
     if caption is None:
-        caption = r"High resolution version available at [gifdome\.dipo\.rocks](https://gifdome.dipo.rocks/)"
+        caption = f"High resolution version available at {public_link()}"
     if parse_mode is None:
         parse_mode = PARSEMODE_MARKDOWN_V2
 
@@ -439,13 +1141,8 @@ def update_bracket_image():
     coords = {128: (82, 82), 129: (82, 222), 130: (82, 362), 131: (82, 502), 132: (82, 642), 133: (82, 782), 134: (82, 922), 135: (82, 1062), 136: (82, 1202), 137: (82, 1342), 138: (82, 1482), 139: (82, 1622), 140: (82, 1762), 141: (82, 1902), 142: (82, 2042), 143: (82, 2182), 144: (82, 2322), 145: (82, 2462), 146: (82, 2602), 147: (82, 2742), 148: (82, 2882), 149: (82, 3022), 150: (82, 3162), 151: (82, 3302), 152: (82, 3442), 153: (82, 3582), 154: (82, 3722), 155: (82, 3862), 156: (82, 4002), 157: (82, 4142), 158: (82, 4282), 159: (82, 4422), 160: (6334, 82), 161: (6334, 222), 162: (6334, 362), 163: (6334, 502), 164: (6334, 642), 165: (6334, 782), 166: (6334, 922), 167: (6334, 1062), 168: (6334, 1202), 169: (6334, 1342), 170: (6334, 1482), 171: (6334, 1622), 172: (6334, 1762), 173: (6334, 1902), 174: (6334, 2042), 175: (6334, 2182), 176: (6334, 2322), 177: (6334, 2462), 178: (6334, 2602), 179: (6334, 2742), 180: (6334, 2882), 181: (6334, 3022), 182: (6334, 3162), 183: (6334, 3302), 184: (6334, 3442), 185: (6334, 3582), 186: (6334, 3722), 187: (6334, 3862), 188: (6334, 4002), 189: (6334, 4142), 190: (6334, 4282), 191: (6334, 4422), 192: (464, 152), 193: (464, 432), 194: (464, 712), 195: (464, 992), 196: (464, 1272), 197: (464, 1552), 198: (464, 1832), 199: (464, 2112), 200: (464, 2392), 201: (464, 2672), 202: (464, 2952), 203: (464, 3232), 204: (464, 3512), 205: (464, 3792), 206: (464, 4072), 207: (464, 4352), 208: (5952, 152), 209: (5952, 432), 210: (5952, 712), 211: (5952, 992), 212: (5952, 1272), 213: (5952, 1552), 214: (5952, 1832), 215: (5952, 2112), 216: (5952, 2392), 217: (5952, 2672), 218: (5952, 2952), 219: (5952, 3232), 220: (5952, 3512), 221: (5952, 3792), 222: (5952, 4072), 223: (5952, 4352), 224: (846, 292), 225: (846, 852), 226: (846, 1412), 227: (846, 1972), 228: (846, 2532), 229: (846, 3092), 230: (846, 3652), 231: (846, 4212), 232: (5570, 292), 233: (5570, 852), 234: (5570, 1412), 235: (5570, 1972), 236: (5570, 2532), 237: (5570, 3092), 238: (5570, 3652), 239: (5570, 4212), 240: (1099, 508), 241: (1099, 1628), 242: (1099, 2748), 243: (1099, 3868), 244: (5189, 508), 245: (5189, 1628), 246: (5189, 2748), 247: (5189, 3868), 248: (1611, 1068), 249: (1611, 3308), 250: (4677, 1068), 251: (4677, 3308), 252: (2180, 1528), 253: (3852, 2592), 254: (3016, 2060)}
     sizes = {128: 128, 129: 128, 130: 128, 131: 128, 132: 128, 133: 128, 134: 128, 135: 128, 136: 128, 137: 128, 138: 128, 139: 128, 140: 128, 141: 128, 142: 128, 143: 128, 144: 128, 145: 128, 146: 128, 147: 128, 148: 128, 149: 128, 150: 128, 151: 128, 152: 128, 153: 128, 154: 128, 155: 128, 156: 128, 157: 128, 158: 128, 159: 128, 160: 128, 161: 128, 162: 128, 163: 128, 164: 128, 165: 128, 166: 128, 167: 128, 168: 128, 169: 128, 170: 128, 171: 128, 172: 128, 173: 128, 174: 128, 175: 128, 176: 128, 177: 128, 178: 128, 179: 128, 180: 128, 181: 128, 182: 128, 183: 128, 184: 128, 185: 128, 186: 128, 187: 128, 188: 128, 189: 128, 190: 128, 191: 128, 192: 128, 193: 128, 194: 128, 195: 128, 196: 128, 197: 128, 198: 128, 199: 128, 200: 128, 201: 128, 202: 128, 203: 128, 204: 128, 205: 128, 206: 128, 207: 128, 208: 128, 209: 128, 210: 128, 211: 128, 212: 128, 213: 128, 214: 128, 215: 128, 216: 128, 217: 128, 218: 128, 219: 128, 220: 128, 221: 128, 222: 128, 223: 128, 224: 128, 225: 128, 226: 128, 227: 128, 228: 128, 229: 128, 230: 128, 231: 128, 232: 128, 233: 128, 234: 128, 235: 128, 236: 128, 237: 128, 238: 128, 239: 128, 240: 256, 241: 256, 242: 256, 243: 256, 244: 256, 245: 256, 246: 256, 247: 256, 248: 256, 249: 256, 250: 256, 251: 256, 252: 512, 253: 512, 254: 512}
 
-    def pad_sticker(sticker):
-        img = Image.new("RGBA", (512, 512))
-        img.paste(sticker, ((512 - sticker.width) // 2, (512 - sticker.height) // 2))
-        return img
-
-    def resize_padded(sticker, size):
-        return sticker.resize((size, size), resample=Image.LANCZOS)
+    def resize_square(gif, size):
+        return ImageOps.pad(gif.convert("RGBA"), (size, size), method=Image.LANCZOS)
 
     bracket = Image.open(project_path / "bracket-template.png")
     img = Image.new("RGBA", bracket.size)
@@ -456,15 +1153,81 @@ def update_bracket_image():
             for i, match in enumerate(matches):
                 if i < 128 or match["winner"] is None:
                     continue
-                cur.execute('SELECT "file_id" FROM "stickers" WHERE "id" = %s', (match["winner"],))
-                file_id, = cur.fetchone()
-                with Image.open(project_path / "stickers" / f"{file_id}.webp") as sticker:
-                    sticker = resize_padded(pad_sticker(sticker), sizes[i])
-                    img.paste(sticker, coords[i])
+                with Image.open(gif_file_path(cur, match["winner"])) as gif:
+                    img.paste(resize_square(gif, sizes[i]), coords[i])
     img.paste(bracket, mask=bracket)
-    img.save(project_path / "bracket.png")
+    part_path = temp_path(project_path / "bracket.png")
+    img.save(part_path, format="PNG")
+    os.replace(part_path, project_path / "bracket.png")
+
+
+def split_message(lines, limit=4000):
+    # Joins lines into as few messages as fit under Telegram's length limit
+    chunk = []
+    chunk_length = 0
+    for line in lines:
+        if chunk and chunk_length + len(line) + 1 > limit:
+            yield "\n".join(chunk)
+            chunk = []
+            chunk_length = 0
+        chunk.append(line)
+        chunk_length += len(line) + 1
+    if chunk:
+        yield "\n".join(chunk)
+
+
+def printbracket_command(update, context):
+    chat_id = update.effective_chat.id
+    try:
+        matches = json.loads(redis.get("matches"))
+    except TypeError:
+        context.bot.send_message(chat_id=chat_id, text="Voting hasn't started yet.")
+        return
+
+    name_width = 20
+    names = {}
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT "gif_id", min("filename") FROM "gif_filenames" GROUP BY "gif_id"')
+            for gif_id, filename in cur:
+                names[gif_id] = filename
+
+    def short_name(gif_id):
+        if gif_id is None:
+            return "?"
+        name = names.get(gif_id) or gif_id
+        if len(name) > name_width:
+            name = name[:name_width - 1] + "\u2026"
+        return name
+
+    lines = []
+    for i, match in enumerate(matches):
+        if i == 0 or round_name(i) != round_name(i - 1):
+            if lines:
+                lines.append("")
+            lines.append(round_name(i).capitalize())
+        participants = match_participants(i, matches)
+        if all(gif_id is None for gif_id in participants):
+            continue
+        a, b = (short_name(gif_id) for gif_id in participants)
+        line = f"{i + 1:3} {a:<{name_width}} vs {b:<{name_width}}"
+        if match["winner"] is not None:
+            line += f" \u2192 {short_name(match['winner'])}"
+        lines.append(line.rstrip())
+
+    # Only backslashes and backticks need escaping inside a code block
+    lines = [re.sub(r"[\\`]", r"\\\g<0>", line) for line in lines]
+    for text in split_message(lines):
+        context.bot.send_message(
+            chat_id=chat_id,
+            text=f"```\n{text}\n```",
+            parse_mode=PARSEMODE_MARKDOWN_V2,
+        )
 
 
+printbracket_handler = CommandHandler(command="printbracket", callback=printbracket_command)
+dispatcher.add_handler(printbracket_handler)
+
 
 def help_command(update, context):
     lines = [
@@ -472,12 +1235,16 @@ def help_command(update, context):
     ]
 
     state = redis.get("state")
-    if state == State.TAKING_SUBMISSIONS.value:
+    if redis.get("title") is not None:
+        lines.append(f"*{markdown_escape(gifdome_name())}*")
+    if state == State.TAKING_SUBMISSIONS.value and redis.get("mode") == b"collect":
+        lines.append(r"Currently collecting GIFs\. This GIFdome has no voting phase\.")
+    elif state == State.TAKING_SUBMISSIONS.value:
         lines.append(r"Currently in submission phase\. The most submitted GIFs advance to the voting phase\.")
     elif state == State.VOTING.value:
         lines.append(r"Currently in voting phase\. See the pinned message for the latest poll\.")
     elif state == State.ENDED.value:
-        lines.append(r"This GIFdome has ended\.")
+        lines.append(markdown_escape(f"This {gifdome_name()} has ended."))
 
     context.bot.send_message(
         chat_id=update.effective_chat.id,
@@ -503,6 +1270,35 @@ def match_participants(index, matches):
     return participants
 
 
+def separate_duplicate_opponents(seeding):
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT "duplicate_id", "primary_id" FROM "duplicates"')
+            primary_ids = dict(cur.fetchall())
+
+    def group(gif_id):
+        return primary_ids.get(gif_id, gif_id)
+
+    seeding = list(seeding)
+    unresolved = 0
+    for i in range(0, len(seeding), 2):
+        if group(seeding[i]) != group(seeding[i + 1]):
+            continue
+        # Swap the second participant with the closest seed that doesn't create a new conflict
+        candidates = sorted(range(1, len(seeding), 2), key=lambda j: abs(j - (i + 1)))
+        for j in candidates:
+            if j == i + 1:
+                continue
+            if group(seeding[j]) != group(seeding[i]) and group(seeding[i + 1]) != group(seeding[j - 1]):
+                seeding[i + 1], seeding[j] = seeding[j], seeding[i + 1]
+                break
+        else:
+            unresolved += 1
+    if unresolved:
+        logging.warning(f"Could not separate {unresolved} first-round duplicate pairing{plural(unresolved)}")
+    return seeding
+
+
 def generate_matches():
     next = [128, 160, 144, 176, 184, 152, 168, 136, 140, 172, 156, 188, 180, 148, 164, 132, 134, 166, 150, 182, 190, 158, 174, 142, 138, 170, 154, 186, 178, 146, 162, 130, 131, 163, 147, 179, 187, 155, 171, 139, 143, 175, 159, 191, 183, 151, 167, 135, 133, 165, 149, 181, 189, 157, 173, 141, 137, 169, 153, 185, 177, 145, 161, 129, 129, 161, 145, 177, 185, 153, 169, 137, 141, 173, 157, 189, 181, 149, 165, 133, 135, 167, 151, 183, 191, 159, 175, 143, 139, 171, 155, 187, 179, 147, 163, 131, 130, 162, 146, 178, 186, 154, 170, 138, 142, 174, 158, 190, 182, 150, 166, 134, 132, 164, 148, 180, 188, 156, 172, 140, 136, 168, 152, 184, 176, 144, 160, 128]
     matches = [
@@ -513,56 +1309,216 @@ def generate_matches():
         for i in range(128, 255)
     )
     matches[-1]["next"] = None
-    hour = 3600
+    min_votes = per_round_min_votes(config)
     for i, match in enumerate(matches):
-        if i < 192:
-            match["duration"] = hour // 2
-        elif i < 224:
-            match["duration"] = hour
-        elif i < 240:
-            match["duration"] = 2 * hour
-        elif i < 248:
-            match["duration"] = 3 * hour
-        elif i < 252:
-            match["duration"] = 6 * hour
-        elif i < 254:
-            match["duration"] = 12 * hour
-        else:
-            match["duration"] = 24 * hour
+        match["duration"] = config.get("round_durations", default_round_durations)[round_index(i)]
+        match["min_votes"] = min_votes[round_index(i)]
     if DEBUG:
         for match in matches:
             match["duration"] = config["debug"]["match_duration"]
+    if (max_duration := config.get("max_match_duration")) is not None:
+        for i, match in enumerate(matches):
+            if match["duration"] > max_duration:
+                logging.info(f"Clamping duration of match {i + 1} to {max_duration} seconds")
+                match["duration"] = max_duration
     return matches
 
 
-def voting_command(update, context):
-    if redis.get("state") != State.TAKING_SUBMISSIONS.value:
-        context.bot.send_message(
-            chat_id=update.effective_chat.id,
-            text="The GIFdome must be in submission phase to start voting.",
-        )
-        return
-
-    if not True:
-        with db:
-            with db.cursor() as cur:
-                cur.execute('SELECT "id" FROM "stickers" LIMIT 256')
-                seed = [id for id, in cur]
-                redis.set("seeding", json.dumps(seed))
-
-    if redis.get("seeding") is None:
+def ordered_submissions(seeding_mode):
+    with db:
+        with db.cursor() as cur:
+            cur.execute(
+                """
+                SELECT "gif_id", count(*), min("created") FROM "submissions"
+                WHERE "gif_id" NOT IN (SELECT "gif_id" FROM "blocked_gifs")
+                GROUP BY "gif_id"
+                """
+            )
+            rows = cur.fetchall()
+
+    return order_submissions(rows, seeding_mode, rng)
+
+
+def contest_info_command(update, context):
+    chat_id = update.effective_chat.id
+    if redis.get("state") in [None, State.NOT_STARTED.value]:
+        context.bot.send_message(chat_id=chat_id, text=f"There{apos}s no GIFdome running right now.")
+        return
+
+    try:
+        matches = json.loads(redis.get("matches"))
+    except TypeError:
+        matches = generate_matches()
+
+    round_durations = {}
+    round_min_votes = {}
+    for i, match in enumerate(matches):
+        round_durations.setdefault(round_name(i), match["duration"])
+        round_min_votes.setdefault(round_name(i), match_min_votes(match))
+
+    per_round_votes = len(set(round_min_votes.values())) > 1
+    if per_round_votes:
+        min_votes_text = "enough votes"
+    else:
+        min_votes_text = f"at least {next(iter(round_min_votes.values()))} votes"
+    lines = [
+        markdown_escape(f"256 GIFs compete in {len(round_durations)} rounds, {len(matches)} polls in total."),
+        markdown_escape(
+            "A poll can be closed once it has been open long enough, "
+            f"has {min_votes_text} and isn{apos}t tied."
+        ),
+        "*Poll durations*",
+    ]
+    for name, seconds in round_durations.items():
+        votes_text = f", at least {round_min_votes[name]} votes" if per_round_votes else ""
+        lines.append(markdown_escape(f"\u2022 {name}: {duration(seconds)}{votes_text}"))
+    if config.get("tie_break", "coin") == "overtime":
+        lines.append(markdown_escape(
+            f"If a poll is closed tied, it is reopened for {duration(config.get('overtime_duration', 600))} "
+            f"up to {config.get('max_overtimes', 3)} times before a coin toss decides."
+        ))
+    else:
+        lines.append(r"If a poll is closed tied, a coin toss decides the winner\.")
+    context.bot.send_message(
+        chat_id=chat_id,
+        parse_mode=PARSEMODE_MARKDOWN_V2,
+        text="\n".join(lines),
+    )
+
+
+contest_info_handler = CommandHandler(command="contest_info", callback=contest_info_command)
+dispatcher.add_handler(contest_info_handler)
+
+
+def voting_command(update, context):
+    if redis.get("state") != State.TAKING_SUBMISSIONS.value:
+        context.bot.send_message(
+            chat_id=update.effective_chat.id,
+            text="The GIFdome must be in submission phase to start voting.",
+        )
+        return
+
+    if redis.get("mode") == b"collect":
+        context.bot.send_message(
+            chat_id=update.effective_chat.id,
+            text="This GIFdome only collects GIFs, there is no voting.",
+        )
+        return
+
+    if (seeding_mode := config.get("seeding", "manual")) != "manual":
+        ordered = ordered_submissions(seeding_mode)
+        if seeding_mode == "popularity" and (pending := pending_seeding()) is not None:
+            redis.set("seeding", json.dumps(pending))
+            seed_order = json.loads(redis.get("pending_seed_order") or "[]")
+        elif len(ordered) < 256:
+            with db:
+                with db.cursor() as cur:
+                    # Only duplicates someone actually sent in, of GIFs that still count
+                    cur.execute(
+                        """
+                        SELECT count(DISTINCT "submitted_gif_id") FROM "submissions"
+                        WHERE "submitted_gif_id" <> "gif_id"
+                            AND "gif_id" NOT IN (SELECT "gif_id" FROM "blocked_gifs")
+                        """
+                    )
+                    merged_count, = cur.fetchone()
+            if len(ordered) + merged_count >= 256:
+                text = (
+                    f"Not enough distinct GIFs: 256 are needed, but only {len(ordered)} are left "
+                    f"after merging {merged_count} duplicate{plural(merged_count)}."
+                )
+            else:
+                text = f"Not enough submissions: 256 GIFs are needed, got {len(ordered)}."
+            context.bot.send_message(chat_id=update.effective_chat.id, text=text)
+            return
+        else:
+            seed_order = ordered[:256]
+            redis.set("seeding", json.dumps(generate_seeding(seed_order)))
+    else:
+        # A manual seeding doesn't rank the GIFs, so rank them by popularity
+        seed_order = ordered_submissions("popularity")
+
+    if redis.get("seeding") is None:
         context.bot.send_message(
             chat_id=update.effective_chat.id,
             text="The bracket must be manually seeded first.",
         )
         return
 
+    if config.get("avoid_duplicate_opponents", False):
+        seeding = separate_duplicate_opponents(json.loads(redis.get("seeding")))
+        redis.set("seeding", json.dumps(seeding))
+
+    # Decides which GIFs advance if the GIFdome runs out of time
+    redis.set("seed_order", json.dumps(seed_order))
     gif_chat_filter.remove_chat_ids(update.effective_chat.id)
     redis.set("state", State.VOTING.value)
     context.bot.send_message(chat_id=update.effective_chat.id, text=f"Submissions closed, it{apos}s voting time!")
     next_match()
 
 
+def submission_count():
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT count(*) FROM "submissions"')
+            count, = cur.fetchone()
+    return count
+
+
+def pending_seeding():
+    # A /reshuffle result only holds while no new GIFs have been submitted
+    if (pending := redis.get("pending_seeding")) is None:
+        return None
+    if redis_get_int("pending_seeding_submissions") != submission_count():
+        return None
+    return json.loads(pending)
+
+
+def reshuffle_command(update, context):
+    chat_id = update.effective_chat.id
+    if redis.get("state") != State.TAKING_SUBMISSIONS.value:
+        context.bot.send_message(chat_id=chat_id, text="The bracket can only be reshuffled before voting starts.")
+        return
+    if config.get("seeding", "manual") != "popularity":
+        context.bot.send_message(chat_id=chat_id, text="Only popularity seeding has random tie-breaks to reshuffle.")
+        return
+
+    count = submission_count()
+    ordered = ordered_submissions("popularity")
+    if len(ordered) < 256:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text=f"Not enough submissions: 256 GIFs are needed, got {len(ordered)}.",
+        )
+        return
+    seeding = generate_seeding(ordered[:256])
+    with redis.pipeline() as pipe:
+        pipe.set("pending_seeding", json.dumps(seeding))
+        pipe.set("pending_seed_order", json.dumps(ordered[:256]))
+        pipe.set("pending_seeding_submissions", count)
+        pipe.execute()
+
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT "gif_id", min("filename") FROM "gif_filenames" GROUP BY "gif_id"')
+            names = dict(cur.fetchall())
+    lines = ["First round pairings, used by /voting unless new GIFs are submitted:"]
+    for i in range(0, len(seeding), 2):
+        a = names.get(seeding[i]) or seeding[i]
+        b = names.get(seeding[i + 1]) or seeding[i + 1]
+        lines.append(f"{i // 2 + 1}. {a} vs {b}")
+    for text in split_message(lines):
+        context.bot.send_message(chat_id=chat_id, text=text)
+
+
+reshuffle_handler = CommandHandler(
+    command="reshuffle",
+    callback=reshuffle_command,
+    filters=Filters.user(username=config["admins"]) & Filters.chat_type.groups,
+)
+dispatcher.add_handler(reshuffle_handler)
+
+
 voting_handler = CommandHandler(
     command="voting",
     callback=voting_command,
@@ -571,74 +1527,209 @@ voting_handler = CommandHandler(
 dispatcher.add_handler(voting_handler)
 
 
-def generate_versus_image(file_id_a, file_id_b, out):
-    with Image.open(project_path / "stickers" / f"{file_id_a}.webp") as img_a, Image.open(project_path / "stickers" / f"{file_id_b}.webp") as img_b:
+def fit_versus_image(img, size=512):
+    mode = config.get("versus_fit", "pad")
+    if mode == "crop":
+        return ImageOps.fit(img, (size, size), method=Image.LANCZOS)
+    if mode == "stretch":
+        return img.resize((size, size), resample=Image.LANCZOS)
+    return ImageOps.contain(img, (size, size), method=Image.LANCZOS)
+
+
+def generate_versus_image(file_path_a, file_path_b, out):
+    # Only the first frame of each GIF is shown
+    with Image.open(file_path_a) as img_a, Image.open(file_path_b) as img_b:
+        img_a = fit_versus_image(img_a)
+        img_b = fit_versus_image(img_b)
         img = Image.open(project_path / "versus-template.png")
         img.paste(img_a, ((512 - img_a.width) // 2, (512 - img_a.height) // 2 + 100))
         img.paste(img_b, ((512 - img_b.width) // 2 + 512 + 20, (512 - img_b.height) // 2 + 100))
         img.save(out, format="PNG")
 
 
-def new_poll(sticker_ids, match_duration):
+def poll_order(participants):
+    return presentation_order(participants, redis.get("current_order_swapped") == b"1")
+
+
+def new_poll(gif_ids, match_duration, *, started_at=None):
     if (group_id := redis_get_int("group_id")) is None:
         raise ValueError("Missing or invalid group_id")
 
+    # Showing the bracket's first GIF as option A every time favors it
+    swapped = config.get("poll", {}).get("randomize_order", False) and rng.random() < 0.5
+    gif_ids = presentation_order(gif_ids, swapped)
+
     file_ids = []
-    sticker_set_ids = []
-    sticker_set_titles = []
+    file_paths = []
+    filenames = []
     with db:
         with db.cursor() as cur:
-            print("sticker ids:", sticker_ids)
-            for sticker_unique_id in sticker_ids:
-                cur.execute(
-                    """
-                    SELECT "stickers"."file_id", "stickers"."set", "sticker_sets"."title"
-                    FROM "stickers" LEFT JOIN "sticker_sets"
-                        ON "stickers"."set" = "sticker_sets"."id"
-                    WHERE "stickers"."id" = %s
-                    """,
-                    (sticker_unique_id,))
-                file_id, set_id, set_title = cur.fetchone()
+            print("gif ids:", gif_ids)
+            for gif_id in gif_ids:
+                if (file_id := gif_file_id(cur, gif_id)) is None:
+                    raise Exception(f"GIF {gif_id} in the bracket is missing from gifs")
                 file_ids.append(file_id)
-                sticker_set_ids.append(set_id)
-                sticker_set_titles.append(set_title)
+                file_paths.append(gif_file_path(cur, gif_id))
+                cur.execute('SELECT min("filename") FROM "gif_filenames" WHERE "gif_id" = %s', (gif_id,))
+                filename, = cur.fetchone()
+                filenames.append(filename)
+
+    options = current_poll_options()
 
     def caption_line(index):
-        emoji = [emoji_a, emoji_b][index]
-        set_title = sticker_set_titles[index] or "this pack"
-        if set_id := sticker_set_ids[index]:
-            return fr"Sticker {emoji} is from [{markdown_escape(set_title)}](https://t.me/addstickers/{set_id})\."
-        return fr"Sticker {emoji} has no pack\."
-
-    caption = "\n".join([
-        r"A new battle begins\!",
-        caption_line(0),
-        caption_line(1),
-        fr"This poll will stay open for at least {_duration(match_duration)}\.",
-    ])
-
-    with io.BytesIO() as img:
-        generate_versus_image(*file_ids, img)
+        emoji = markdown_escape(options[index])
+        if filename := filenames[index]:
+            return fr"GIF {emoji} is {markdown_escape(filename)}\."
+        return fr"GIF {emoji} has no filename\."
+
+    def build_caption():
+        return "\n".join([
+            r"A new battle begins\!",
+            caption_line(0),
+            caption_line(1),
+            fr"This poll will stay open for at least {duration(match_duration)}\.",
+        ])
+
+    caption = build_caption()
+
+    reply_kwargs = {}
+    if config.get("poll", {}).get("thread", False):
+        # Chains the new poll to the previous winner announcement
+        if (announcement_id := redis_get_int("last_outbox_message")) is not None:
+            reply_kwargs = {
+                "reply_to_message_id": announcement_id,
+                "allow_sending_without_reply": True,
+            }
+    redis.delete("last_outbox_message")
+
+    preview = config.get("poll", {}).get("preview", "versus")
+    versus_image = None
+    if preview == "versus":
+        with io.BytesIO() as img:
+            try:
+                generate_versus_image(*file_paths, img)
+                versus_image = img.getvalue()
+            except OSError as e:
+                # Pillow can't read MP4 GIFs, and the download may have failed
+                logging.warning(f"Could not generate the versus image, sending the GIFs separately: {e}")
+                preview = "separate"
+    if preview == "separate":
+        # Skips building the versus image; the caption becomes its own message
+        for i, file_id in enumerate(file_ids):
+            bot.send_animation(chat_id=group_id, animation=file_id, **(reply_kwargs if i == 0 else {}))
+        stickers_message = bot.send_message(
+            chat_id=group_id,
+            text=caption,
+            parse_mode=PARSEMODE_MARKDOWN_V2,
+            disable_web_page_preview=True,
+        )
+    else:
         stickers_message = bot.send_photo(
             chat_id=group_id,
-            photo=img.getvalue(),
+            photo=versus_image,
             caption=caption,
             parse_mode=PARSEMODE_MARKDOWN_V2,
+            **reply_kwargs,
         )
-    poll_message = bot.send_poll(
-        chat_id=group_id,
-        question="Which shall win?",
-        options=[emoji_a, emoji_b],
-        reply_to_message_id=stickers_message.message_id
-    )
-    bot.pin_chat_message(chat_id=group_id, message_id=poll_message.message_id)
+    try:
+        poll_message = bot.send_poll(
+            chat_id=group_id,
+            question=poll_question,
+            options=options,
+            reply_to_message_id=stickers_message.message_id
+        )
+    except BadRequest as e:
+        if not re.search(r"poll.*(question|option|answer)", e.message, re.IGNORECASE):
+            raise e
+        # The configured question or options; the defaults are known to work
+        logging.exception(e)
+        notify_admins(f"Telegram rejected the poll ({e.message}), check the poll settings.")
+        options = [emoji_a, emoji_b]
+        poll_message = bot.send_poll(
+            chat_id=group_id,
+            question="Which shall win?",
+            options=options,
+            reply_to_message_id=stickers_message.message_id
+        )
+        # The caption named the rejected options
+        caption = build_caption()
+        if preview == "separate":
+            bot.edit_message_text(
+                chat_id=group_id,
+                message_id=stickers_message.message_id,
+                text=caption,
+                parse_mode=PARSEMODE_MARKDOWN_V2,
+                disable_web_page_preview=True,
+            )
+        else:
+            bot.edit_message_caption(
+                chat_id=group_id,
+                message_id=stickers_message.message_id,
+                caption=caption,
+                parse_mode=PARSEMODE_MARKDOWN_V2,
+            )
+    pin_message(group_id, poll_message.message_id)
     redis.set("current_stickers_message", stickers_message.message_id)
+    redis.set("current_stickers_caption", caption)
+    redis.set("current_poll_labels", json.dumps(options))
+    redis.set("current_preview", preview)
+    redis.set("current_order_swapped", int(swapped))
+    redis.delete("current_votes", "current_caption_votes")
     redis.set("current_poll_message", poll_message.message_id)
     redis.set("current_poll", poll_message.poll.id)
-    redis.set("current_poll_start", _now())
+    # A poll sent again keeps its original deadline
+    redis.set("current_poll_start", started_at if started_at is not None else now())
     redis.set("current_voter_count", 0)
 
 
+def update_vote_caption(context):
+    if redis.get("state") != State.VOTING.value:
+        return
+    votes = redis.get("current_votes")
+    if votes is None or votes == redis.get("current_caption_votes"):
+        return
+    message_id = redis_get_int("current_stickers_message")
+    caption = redis.get("current_stickers_caption")
+    if message_id is None or caption is None:
+        return
+
+    votes_a, votes_b = json.loads(votes)
+    option_a, option_b = current_poll_labels()
+    text = caption.decode() + markdown_escape(f"\nVotes: {option_a} {votes_a} \u2013 {votes_b} {option_b}")
+    try:
+        if redis.get("current_preview") == b"separate":
+            bot.edit_message_text(
+                chat_id=redis_get_int("group_id"),
+                message_id=message_id,
+                text=text,
+                parse_mode=PARSEMODE_MARKDOWN_V2,
+                disable_web_page_preview=True,
+            )
+        else:
+            bot.edit_message_caption(
+                chat_id=redis_get_int("group_id"),
+                message_id=message_id,
+                caption=text,
+                parse_mode=PARSEMODE_MARKDOWN_V2,
+            )
+    except BadRequest as e:
+        if not e.message.startswith("Message is not modified"):
+            raise e
+    redis.set("current_caption_votes", votes)
+
+
+if config.get("live_votes", {}).get("enabled", False):
+    updater.job_queue.run_repeating(
+        update_vote_caption,
+        interval=config["live_votes"].get("interval", 30),
+    )
+
+
+def match_min_votes(match):
+    # Brackets generated before per-round minimums use the flat one
+    return match.get("min_votes", config["min_votes"])
+
+
 def current_match():
     if (index := redis_get_int("current_match")) is None:
         return None
@@ -652,7 +1743,9 @@ def current_match():
         return None
 
 
-def next_match():
+def next_match(forced_winner_id=None, *, time_cap=None):
+    # With time_cap, the current poll is the last one and the rest of the
+    # bracket is resolved without polls
     current_match_index = redis_get_int("current_match")
     print("called next_match; current_match_index is", current_match_index)
 
@@ -673,9 +1766,16 @@ def next_match():
     print("old match is", current_match_index, current_match)
     print("old participants are", current_match_participants)
 
+    if current_match["winner"] is not None and current_match["next"] is not None:
+        # The winner was recorded but starting the next poll failed
+        if time_cap is not None:
+            finish_time_capped(matches, time_cap)
+            return
+        start_next_match(current_match_index)
+        return
+
     if DEBUG and current_match_index < config["debug"]["autovote_until"]:
-        import random
-        winner_id = random.choice(current_match_participants)
+        winner_id = rng.choice(current_match_participants)
         matches[current_match_index]["winner"] = winner_id
         redis.set("matches", json.dumps(matches))
         if not config["debug"]["disable_bracket"]:
@@ -687,65 +1787,553 @@ def next_match():
     current_poll_message_id = redis_get_int("current_poll_message")
     group_id = redis_get_int("group_id")
 
-    old_poll = None
+    votes = None
     if current_poll_message_id is not None:
-        bot.unpin_chat_message(chat_id=group_id, message_id=current_poll_message_id)
+        unpin_message(group_id, current_poll_message_id)
         try:
             old_poll = bot.stop_poll(chat_id=group_id, message_id=current_poll_message_id)
+            votes = [option.voter_count for option in old_poll.options]
         except BadRequest as e:
             if e.message != "Poll has already been closed":
                 raise e
+            # Telegram closed the poll itself; use the last counts seen while it was open
+            if (last_votes := redis.get("current_votes")) is not None:
+                logging.warning(f"Poll for match {current_match_index + 1} was already closed, using the last counts")
+                votes = json.loads(last_votes)
 
-    if old_poll is None:
+    if votes is None:
         bot.send_message(chat_id=group_id, text="Oopsie! This requires some manual attention.")
+        notify_admins(f"The poll for match {current_match_index + 1} could not be stopped.")
         return
     else:
-        votes_a = old_poll.options[0].voter_count
-        votes_b = old_poll.options[1].voter_count
-        if votes_a > votes_b:
+        # Back from poll order to bracket order
+        votes_a, votes_b = presentation_order(votes, redis.get("current_order_swapped") == b"1")
+        if forced_winner_id is not None:
+            winner_id = forced_winner_id
+        elif votes_a > votes_b:
             winner_id = current_match_participants[0]
         elif votes_a < votes_b:
             winner_id = current_match_participants[1]
+        elif (
+            config.get("tie_break", "coin") == "overtime"
+            and time_cap is None
+            and current_match.get("overtimes", 0) < config.get("max_overtimes", 3)
+        ):
+            overtime_duration = config.get("overtime_duration", 600)
+            current_match["overtimes"] = current_match.get("overtimes", 0) + 1
+            current_match["duration"] = overtime_duration
+            redis.set("matches", json.dumps(matches))
+            bot.send_message(
+                chat_id=group_id,
+                text=f"It{apos}s a tie! Sudden death: vote again in the next {duration(overtime_duration)}.",
+            )
+            new_poll(current_match_participants, overtime_duration)
+            return
         else:
             # Tiebreaker
             bot.send_message(chat_id=group_id, text="Tossing a coin to determine the winner.")
-            winner_id = current_match_participants[secrets.randbelow(2)]
-
-    with db:
-        with db.cursor() as cur:
-            cur.execute('SELECT "file_id" FROM "stickers" WHERE "id" = %s', (winner_id,))
-            winner_file_id, = cur.fetchone()
+            winner_id = current_match_participants[rng.randrange(2)]
 
     matches[current_match_index]["winner"] = winner_id
     matches[current_match_index]["votes"] = [votes_a, votes_b]
-    redis.set("matches", json.dumps(matches))
-    update_bracket_image()
+    if time_cap is not None and current_match["next"] is not None:
+        finish_time_capped(matches, time_cap)
+        return
+    record_winner(matches, current_match_index, reply_to_message_id=current_poll_message_id)
 
-    end = current_match["next"] is None
 
-    repeat_sticker = 5 if end else 1
-    for _ in range(repeat_sticker):
-        bot.send_sticker(chat_id=group_id, sticker=winner_file_id)
+def record_winner(matches, match_index, *, reply_to_message_id=None, notice=None):
+    group_id = redis_get_int("group_id")
+    winner_id = matches[match_index]["winner"]
+    with db:
+        with db.cursor() as cur:
+            if (winner_file_id := gif_file_id(cur, winner_id)) is None:
+                raise Exception(f"Winning GIF {winner_id} is missing from gifs")
+
+    end = matches[match_index]["next"] is None
+    credit = None
+    if end and config.get("credit_submitter", False):
+        with db:
+            with db.cursor() as cur:
+                cur.execute(
+                    """
+                    SELECT "users"."username", "users"."show_name"
+                    FROM "submissions" JOIN "users" ON "submissions"."user_id" = "users"."id"
+                    WHERE "submissions"."gif_id" = %s
+                    ORDER BY "submissions"."created" LIMIT 1
+                    """,
+                    (winner_id,),
+                )
+                first_submitter = cur.fetchone()
+        # Only users who asked to be credited with /privacy name on are named
+        if first_submitter is not None and first_submitter[1] and first_submitter[0] is not None:
+            credit = f"First submitted by @{first_submitter[0]}!"
+
+    # Queue the announcement in the same transaction as the result so it can't get lost
+    with redis.pipeline() as pipe:
+        pipe.set("matches", json.dumps(matches))
+        if notice is not None:
+            enqueue_message(pipe, "send_message", chat_id=group_id, text=notice)
+        repeat_winner = 5 if end else 1
+        reply_kwargs = {}
+        if config.get("reply_to_poll", False) and reply_to_message_id is not None:
+            # Still sent if the poll message was deleted in the meantime
+            reply_kwargs = {
+                "reply_to_message_id": reply_to_message_id,
+                "allow_sending_without_reply": True,
+            }
+        for i in range(repeat_winner):
+            enqueue_message(
+                pipe,
+                "send_animation",
+                chat_id=group_id,
+                animation=winner_file_id,
+                **(reply_kwargs if i == 0 else {}),
+            )
+        if credit is not None:
+            enqueue_message(pipe, "send_message", chat_id=group_id, text=credit)
+        if end:
+            pipe.set("current_match", match_index)
+            pipe.set("state", State.ENDED.value)
+            pipe.set("ended_at", now())
+            # Kept across resets for /winner
+            pipe.set("last_winner", winner_id)
+        else:
+            enqueue_message(pipe, "send_message", chat_id=group_id, text="We have a winner!")
+        pipe.execute()
+    drain_outbox()
+    update_bracket_image()
 
     if end:
-        redis.set("state", State.ENDED.value)
         update_chat_description()
         send_bracket(
             chat_id=group_id,
-            caption=r"Ohi on\! kiitos pelaamisesta vaikka äänestitte VÄÄRIN",
+            caption=(
+                f"{markdown_escape(gifdome_name())} on ohi\\! kiitos pelaamisesta vaikka äänestitte VÄÄRIN"
+                f"\n\nFull results at {public_link()}"
+            ),
         )
+        if config.get("rolling", {}).get("enabled", False):
+            start_next_gifdome(group_id, winner_id)
     else:
-        bot.send_message(chat_id=group_id, text="We have a winner!")
+        start_next_match(match_index)
+
+
+def finish_time_capped(matches, time_cap):
+    # The rest of the bracket goes to the higher seed without polls. Seeds are
+    # ranked by the order /voting seeded from; GIFs missing from it rank last.
+    seed_order = json.loads(redis.get("seed_order") or "[]")
+    seed_rank = {gif_id: rank for rank, gif_id in enumerate(seed_order)}
+    for i, match in enumerate(matches):
+        if match["winner"] is None:
+            match["winner"] = min(
+                match_participants(i, matches),
+                key=lambda gif_id: seed_rank.get(gif_id, len(seed_rank)),
+            )
+    record_winner(
+        matches,
+        len(matches) - 1,
+        notice=f"Time{apos}s up! This GIFdome can run for at most {duration(time_cap)}, so the remaining matches went to the higher seed.",
+    )
+
+
+def check_total_duration(context):
+    if (time_cap := config.get("max_total_duration")) is None:
+        return
+    # Finishing ends the GIFdome, so the voting state is all that must be checked
+    if redis.get("state") != State.VOTING.value:
+        return
+    if (started_at := redis_get_int("submissions_started_at")) is None:
+        return
+    if now() - started_at < time_cap:
+        return
+    # A failed attempt (e.g. the poll couldn't be stopped) already told the
+    # admins, so it's only retried hourly instead of on every check
+    if not redis.set("time_cap_attempt", 1, nx=True, ex=3600):
+        return
+
+    if (match := current_match()) is not None and match["winner"] is not None:
+        # Between polls; finish instead of waiting for the next one
+        with next_poll_lock:
+            if (match := current_match()) is not None and match["winner"] is not None:
+                redis.delete("next_poll_at")
+                finish_time_capped(json.loads(redis.get("matches")), time_cap)
+                return
+    next_match(time_cap=time_cap)
+
+
+# Always scheduled so max_total_duration can be set by reloading the config
+updater.job_queue.run_repeating(check_total_duration, interval=60)
+
+
+def quiet_hours_end(timestamp):
+    quiet_hours = config.get("quiet_hours", {})
+    if not quiet_hours.get("enabled", False):
+        return None
+
+    local = datetime.fromtimestamp(timestamp, ZoneInfo(quiet_hours.get("timezone", "UTC")))
+    start_hour, start_minute = parse_clock_time(quiet_hours["start"])
+    end_hour, end_minute = parse_clock_time(quiet_hours["end"])
+    start = start_hour * 60 + start_minute
+    end = end_hour * 60 + end_minute
+    minute_of_day = local.hour * 60 + local.minute
+    if start <= end:
+        quiet = start <= minute_of_day < end
+    else:
+        # The quiet hours span midnight
+        quiet = minute_of_day >= start or minute_of_day < end
+    if not quiet:
+        return None
+
+    end_time = local.replace(hour=end_hour, minute=end_minute, second=0, microsecond=0)
+    if end_time <= local:
+        end_time += timedelta(days=1)
+    return int(end_time.timestamp())
+
+
+def start_next_match(current_match_index):
+    if (poll_at := redis_get_int("next_poll_at")) is None:
+        poll_at = now() + config.get("inter_match_delay", 0)
+        if (quiet_end := quiet_hours_end(poll_at)) is not None:
+            poll_at = quiet_end
+            bot.send_message(
+                chat_id=redis_get_int("group_id"),
+                text=f"Shh, it{apos}s quiet hours. The next poll starts in {duration(poll_at - now())}.",
+            )
+        if poll_at <= now():
+            send_next_poll(current_match_index)
+            return
+        redis.set("next_poll_at", poll_at)
+    updater.job_queue.run_once(
+        lambda context: send_next_poll(current_match_index),
+        max(poll_at - now(), 0),
+    )
+
+
+next_poll_lock = threading.Lock()
+
+
+def send_next_poll(current_match_index):
+    with next_poll_lock:
+        # The next poll may have been scheduled more than once
+        if redis_get_int("current_match") != current_match_index:
+            return
+        matches = json.loads(redis.get("matches"))
         new_match_index = current_match_index + 1
         new_match = matches[new_match_index]
         new_participants = match_participants(new_match_index, matches)
         new_poll(new_participants, new_match["duration"])
         redis.set("current_match", new_match_index)
+        redis.delete("next_poll_at")
         update_chat_description()
 
 
-def poll_update(update, context):
-    poll = update.poll
+def reopen_command(update, context):
+    chat_id = update.effective_chat.id
+    window = config.get("reopen_window", 3600)
+    ended_at = redis_get_int("ended_at")
+    if redis.get("state") != State.ENDED.value or ended_at is None or now() - ended_at > window:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text=f"Only a GIFdome that ended in the last {duration(window)} can be reopened.",
+        )
+        return
+
+    if context.args != ["confirm"]:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text="This reopens the final with a fresh poll. Send /reopen confirm to continue.",
+        )
+        return
+
+    match_index = redis_get_int("current_match")
+    matches = json.loads(redis.get("matches"))
+    matches[match_index]["winner"] = None
+    matches[match_index].pop("votes", None)
+    with redis.pipeline() as pipe:
+        pipe.set("matches", json.dumps(matches))
+        pipe.set("state", State.VOTING.value)
+        pipe.delete("ended_at")
+        pipe.execute()
+
+    context.bot.send_message(chat_id=chat_id, text="The final has been reopened!")
+    new_poll(match_participants(match_index, matches), matches[match_index]["duration"])
+    update_chat_description()
+
+
+reopen_handler = CommandHandler(
+    command="reopen",
+    callback=reopen_command,
+    filters=Filters.chat_type.groups & Filters.user(username=config["admins"]),
+)
+dispatcher.add_handler(reopen_handler)
+
+
+def match_command(update, context):
+    chat_id = update.effective_chat.id
+    try:
+        matches = json.loads(redis.get("matches"))
+    except TypeError:
+        context.bot.send_message(chat_id=chat_id, text="Voting hasn't started yet.")
+        return
+
+    try:
+        match_num = int(context.args[0])
+    except (IndexError, ValueError):
+        match_num = None
+    if match_num is None or not 1 <= match_num <= len(matches):
+        context.bot.send_message(chat_id=chat_id, text=f"Usage: /match NUMBER, from 1 to {len(matches)}")
+        return
+
+    index = match_num - 1
+    match = matches[index]
+    current_index = redis_get_int("current_match")
+    lines = [f"Match {match_num} ({round_name(index)})"]
+    if match["winner"] is not None:
+        lines.append("State: finished")
+    elif index == current_index:
+        lines.append("State: started")
+    else:
+        lines.append("State: not started")
+
+    participants = match_participants(index, matches)
+    if index == current_index and match["winner"] is None:
+        participants = poll_order(participants)
+    with db:
+        with db.cursor() as cur:
+            for option, gif_id in zip(current_poll_labels(), participants):
+                if gif_id is None:
+                    lines.append(f"{option}: to be decided")
+                    continue
+                cur.execute('SELECT "filename" FROM "gif_filenames" WHERE "gif_id" = %s', (gif_id,))
+                filenames = ", ".join(filename for filename, in cur) or "no filename"
+                lines.append(f"{option}: {gif_id} ({filenames})")
+
+    if "votes" in match:
+        lines.append(f"Votes: {match['votes'][0]}\u2013{match['votes'][1]}")
+    if match["winner"] is not None:
+        lines.append(f"Winner: {match['winner']}")
+    lines.append(f"Duration: {duration(match['duration'])}")
+    if index == current_index and match["winner"] is None:
+        if (poll_start := redis_get_int("current_poll_start")) is not None:
+            lines.append(f"Started: {datetime.fromtimestamp(poll_start, timezone.utc):%Y-%m-%d %H:%M:%S} UTC")
+        if (poll_id := redis.get("current_poll")) is not None:
+            lines.append(f"Poll: {poll_id.decode()}")
+        lines.append(f"Voters so far: {redis_get_int('current_voter_count') or 0}")
+    context.bot.send_message(chat_id=chat_id, text="\n".join(lines))
+
+
+match_handler = CommandHandler(
+    command="match",
+    callback=match_command,
+    filters=Filters.user(username=config["admins"]),
+)
+dispatcher.add_handler(match_handler)
+
+
+def mystats_command(update, context):
+    user = update.effective_user
+    with db:
+        with db.cursor() as cur:
+            cur.execute(
+                """
+                SELECT "submissions"."gif_id", min("gif_filenames"."filename")
+                FROM "submissions" LEFT JOIN "gif_filenames"
+                    ON "submissions"."gif_id" = "gif_filenames"."gif_id"
+                WHERE "submissions"."user_id" = %s
+                GROUP BY "submissions"."gif_id"
+                """,
+                (user.id,),
+            )
+            submitted = cur.fetchall()
+
+    if not submitted:
+        update.effective_message.reply_text(f"You haven{apos}t submitted any GIFs in this GIFdome.")
+        return
+
+    try:
+        matches = json.loads(redis.get("matches"))
+    except TypeError:
+        update.effective_message.reply_text(
+            f"You{apos}ve submitted {len(submitted)} GIF{plural(len(submitted))}. "
+            "Their results will be here once voting starts."
+        )
+        return
+    participants = [match_participants(i, matches) for i in range(len(matches))]
+
+    lines = []
+    best = None
+    for gif_id, filename in submitted:
+        name = filename or gif_id
+        played = [i for i, gif_ids in enumerate(participants) if gif_id in gif_ids]
+        if not played:
+            lines.append(f"{name}: didn{apos}t make it to the bracket")
+            continue
+        last = played[-1]
+        match = matches[last]
+        if match["winner"] is None:
+            lines.append(f"{name}: still in, playing in the {round_name(last)}")
+        elif match["winner"] == gif_id:
+            lines.append(f"{name}: won the GIFdome!")
+        else:
+            votes = sorted(match.get("votes", [0, 0]), reverse=True)
+            lines.append(f"{name}: eliminated in the {round_name(last)}, {votes[0]}\u2013{votes[1]}")
+        if best is None or last > best[0]:
+            best = (last, name)
+
+    if best is not None:
+        lines.append(f"Your best GIF: {best[1]}")
+    update.effective_message.reply_text("\n".join(lines))
+
+
+mystats_handler = CommandHandler(
+    command="mystats",
+    callback=mystats_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(mystats_handler)
+
+
+def winner_command(update, context):
+    message = update.effective_message
+    if (winner_id := redis.get("last_winner")) is None:
+        message.reply_text("No GIFdome has finished yet.")
+        return
+
+    with db:
+        with db.cursor() as cur:
+            file_id = gif_file_id(cur, winner_id.decode())
+    if file_id is None:
+        message.reply_text(f"The winning GIF isn{apos}t available anymore.")
+        return
+    context.bot.send_animation(
+        chat_id=update.effective_chat.id,
+        animation=file_id,
+        caption="The winner of the latest GIFdome!",
+    )
+
+
+winner_handler = CommandHandler(
+    command="winner",
+    callback=winner_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(winner_handler)
+
+
+def report_command(update, context):
+    message = update.effective_message
+    if update.effective_chat.id != redis_get_int("group_id"):
+        message.reply_text("GIFs can only be reported in the GIFdome group.")
+        return
+    if redis.get("state") != State.VOTING.value:
+        message.reply_text("GIFs can only be reported during voting.")
+        return
+    if (match := current_match()) is None or match["winner"] is not None:
+        # Between polls there is no matchup to skip
+        message.reply_text("There is no poll open right now.")
+        return
+
+    current_message_ids = [
+        message_id
+        for message_id in [
+            redis_get_int("current_stickers_message"),
+            redis_get_int("current_poll_message"),
+        ]
+        if message_id is not None
+    ]
+    if message.reply_to_message is None or message.reply_to_message.message_id not in current_message_ids:
+        message.reply_text("Reply to the current poll with /report A or /report B.")
+        return
+
+    option = context.args[0].upper() if context.args else None
+    if option not in ["A", "B"]:
+        message.reply_text("Reply to the current poll with /report A or /report B.")
+        return
+
+    match_index = redis_get_int("current_match")
+    matches = json.loads(redis.get("matches"))
+    participants = poll_order(match_participants(match_index, matches))
+    reported_index = 0 if option == "A" else 1
+    gif_id = participants[reported_index]
+
+    key = f"reports:{gif_id}"
+    user_id = update.effective_user.id
+    threshold = config.get("report_threshold", 3)
+    # The report only counts if the block it triggers is committed too
+    added = redis.sadd(key, user_id)
+    try:
+        report_count = redis.scard(key)
+        if report_count >= threshold:
+            with db:
+                with db.cursor() as cur:
+                    cur.execute(
+                        """
+                        INSERT INTO "blocked_gifs"("gif_id", "created") VALUES (%s, %s)
+                            ON CONFLICT ("gif_id") DO NOTHING
+                        """,
+                        (gif_id, datetime.now(timezone.utc)),
+                    )
+    except Exception:
+        if added:
+            redis.srem(key, user_id)
+        raise
+    if report_count < threshold:
+        message.reply_text(f"Thanks, GIF {option} has been reported ({report_count}/{threshold}).")
+        return
+
+    group_id = redis_get_int("group_id")
+    admins = " ".join(f"@{admin}" for admin in config["admins"])
+    bot.send_message(
+        chat_id=group_id,
+        text=f"GIF {option} has been reported by {report_count} users and is now blocked. {admins}",
+    )
+    notify_admins(f"GIF {gif_id} was blocked after {report_count} reports.")
+    next_match(forced_winner_id=participants[1 - reported_index])
+
+
+report_handler = CommandHandler(
+    command="report",
+    callback=report_command,
+    filters=Filters.chat_type.groups,
+)
+dispatcher.add_handler(report_handler)
+
+
+def margin_met(votes_a, votes_b):
+    # min_margin is either a number of votes or a percentage of all votes like "10%"
+    min_margin = config.get("min_margin", 0)
+    margin = abs(votes_a - votes_b)
+    if isinstance(min_margin, str) and min_margin.endswith("%"):
+        return margin * 100 >= float(min_margin[:-1]) * (votes_a + votes_b)
+    return margin >= min_margin
+
+
+def max_match_duration():
+    return config.get("max_match_duration", max_duration_ceiling)
+
+
+def extend_for_margin():
+    match_index = redis_get_int("current_match")
+    matches = json.loads(redis.get("matches"))
+    match = matches[match_index]
+    if match.get("margin_extensions", 0) >= config.get("max_margin_extensions", 3):
+        return False
+    if match["duration"] >= max_match_duration():
+        return False
+
+    extension = min(config.get("margin_extension", 600), max_match_duration() - match["duration"])
+    match["margin_extensions"] = match.get("margin_extensions", 0) + 1
+    match["duration"] += extension
+    redis.set("matches", json.dumps(matches))
+    bot.send_message(
+        chat_id=redis_get_int("group_id"),
+        text=f"The lead is too narrow to call, this poll stays open for another {duration(extension)}.",
+    )
+    return True
+
+
+def process_poll(poll):
     if poll.is_closed:
         print("this poll is closed")
         return
@@ -759,8 +2347,13 @@ def poll_update(update, context):
         return
 
     redis.set("current_voter_count", poll.total_voter_count)
+    redis.set("current_votes", json.dumps([option.voter_count for option in poll.options]))
 
-    if poll.total_voter_count < config["min_votes"]:
+    match = current_match()
+    if match is None:
+        return
+
+    if poll.total_voter_count < match_min_votes(match):
         print("not enough votes")
         return
 
@@ -772,20 +2365,141 @@ def poll_update(update, context):
     if poll_start is None:
         return
 
-    match = current_match()
-    if match is None:
+    if now() - poll_start < match["duration"]:
         return
 
-    if _now() - poll_start < match["duration"]:
+    if not margin_met(poll.options[0].voter_count, poll.options[1].voter_count) and extend_for_margin():
         return
 
     next_match()
 
 
+def poll_update(update, context):
+    process_poll(update.poll)
+
+
 poll_handler = PollHandler(callback=poll_update)
 dispatcher.add_handler(poll_handler)
 
 
+def poll_time_left():
+    match = current_match()
+    poll_start = redis_get_int("current_poll_start")
+    if match is None or poll_start is None:
+        return None
+    return poll_start + match["duration"] - now()
+
+
+def timeleft_command(update, context):
+    chat_id = update.effective_chat.id
+    if redis.get("state") != State.VOTING.value:
+        context.bot.send_message(chat_id=chat_id, text="There is no poll open right now.")
+        return
+
+    if (time_left := poll_time_left()) is None:
+        context.bot.send_message(chat_id=chat_id, text="There is no poll open right now.")
+        return
+
+    match = current_match()
+    if time_left > 0:
+        text = f"This poll can be closed in {duration(time_left)}."
+    else:
+        text = (
+            "This poll has been open long enough and will be closed once it has at least "
+            f"{match_min_votes(match)} votes and isn{apos}t tied."
+        )
+    min_margin = config.get("min_margin", 0)
+    if min_margin and match.get("margin_extensions", 0) < config.get("max_margin_extensions", 3):
+        lead = min_margin if isinstance(min_margin, str) else f"{min_margin} vote{plural(min_margin)}"
+        text += f" If neither GIF leads by {lead} by then, it stays open longer."
+    context.bot.send_message(chat_id=chat_id, text=text)
+
+
+timeleft_handler = CommandHandler(command="timeleft", callback=timeleft_command)
+dispatcher.add_handler(timeleft_handler)
+
+
+def message_link(chat, message_id):
+    if chat.username:
+        return f"https://t.me/{chat.username}/{message_id}"
+    # Private supergroup IDs are the internal chat ID prefixed with -100
+    chat_id = str(chat.id)
+    if chat_id.startswith("-100"):
+        return f"https://t.me/c/{chat_id[4:]}/{message_id}"
+    return None
+
+
+def vote_command(update, context):
+    chat_id = update.effective_chat.id
+    poll_message_id = redis_get_int("current_poll_message")
+    if redis.get("state") != State.VOTING.value or poll_message_id is None:
+        context.bot.send_message(chat_id=chat_id, text="There is no poll open right now.")
+        return
+
+    group_id = redis_get_int("group_id")
+    if (link := message_link(bot.get_chat(chat_id=group_id), poll_message_id)) is not None:
+        context.bot.send_message(chat_id=chat_id, text=f"Vote here: {link}")
+    elif chat_id == group_id:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text="Vote here!",
+            reply_to_message_id=poll_message_id,
+        )
+    else:
+        context.bot.send_message(chat_id=chat_id, text="The current poll is pinned in the group.")
+
+
+vote_handler = CommandHandler(command="vote", callback=vote_command)
+dispatcher.add_handler(vote_handler)
+
+
+def extend_command(update, context):
+    chat_id = update.effective_chat.id
+    if redis.get("state") != State.VOTING.value or current_match() is None:
+        context.bot.send_message(chat_id=chat_id, text="There is no poll open right now.")
+        return
+
+    max_extension = config.get("max_extension", 24 * 3600)
+    try:
+        seconds = int(context.args[0])
+    except (IndexError, ValueError):
+        seconds = None
+    if seconds is None or not 0 < seconds <= max_extension:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text=f"Usage: /extend SECONDS, at most {max_extension} seconds at a time.",
+        )
+        return
+
+    match_index = redis_get_int("current_match")
+    matches = json.loads(redis.get("matches"))
+    match = matches[match_index]
+    seconds = min(seconds, max_match_duration() - match["duration"])
+    if seconds <= 0:
+        context.bot.send_message(
+            chat_id=chat_id,
+            text=f"This poll can{apos}t be extended, it{apos}s already {duration(match['duration'])} long.",
+        )
+        return
+    match["duration"] += seconds
+    redis.set("matches", json.dumps(matches))
+
+    time_left = poll_time_left()
+    if time_left is not None and time_left > 0:
+        text = f"Poll extended by {duration(seconds)}. It can be closed in {duration(time_left)}."
+    else:
+        text = f"Poll extended by {duration(seconds)}. It can already be closed."
+    context.bot.send_message(chat_id=chat_id, text=text)
+
+
+extend_handler = CommandHandler(
+    command="extend",
+    callback=extend_command,
+    filters=Filters.chat_type.groups & Filters.user(username=config["admins"]),
+)
+dispatcher.add_handler(extend_handler)
+
+
 def next_command(update, context):
     if redis.get("state") != State.VOTING.value:
         return
@@ -800,9 +2514,9 @@ def next_command(update, context):
     if match_index is not None and match_index >= 248:
         if update.effective_user.username not in config["admins"]:
             texts = ["Only admins can use /next at this stage."]
-            if poll_start is not None and _now() - poll_start < match["duration"]:
+            if poll_start is not None and now() - poll_start < match["duration"]:
                     poll_end = poll_start + match["duration"]
-                    texts.append(f"This poll can be closed in {duration(poll_end - _now())}.")
+                    texts.append(f"This poll can be closed in {duration(poll_end - now())}.")
             context.bot.send_message(
                 chat_id=update.effective_chat.id,
                 text=" ".join(texts),
@@ -810,22 +2524,26 @@ def next_command(update, context):
             return
 
     if poll_start is not None:
-        if _now() - poll_start < match["duration"]:
+        if now() - poll_start < match["duration"]:
             poll_end = poll_start + match["duration"]
             context.bot.send_message(
                 chat_id=update.effective_chat.id,
-                text=f"This poll can be closed in {duration(poll_end - _now())}."
+                text=f"This poll can be closed in {duration(poll_end - now())}."
             )
             return
 
     voter_count = redis_get_int("current_voter_count")
-    if voter_count is not None and voter_count < config["min_votes"]:
+    if voter_count is not None and voter_count < match_min_votes(match):
         context.bot.send_message(
             chat_id=update.effective_chat.id,
             text="Not enough votes to change poll."
         )
         return
 
+    if (votes := redis.get("current_votes")) is not None:
+        if not margin_met(*json.loads(votes)) and extend_for_margin():
+            return
+
     next_match()
 
 
@@ -844,6 +2562,93 @@ end_handler = CommandHandler(
 dispatcher.add_handler(end_handler)
 
 
+def parse_command_options(args):
+    # Values with spaces can be quoted, e.g. title="Halloween GIFdome"
+    options = {}
+    for arg in shlex.split(" ".join(args)):
+        key, sep, value = arg.partition("=")
+        if not sep:
+            raise ValueError(f"Invalid option {arg!r}")
+        options[key] = value
+    return options
+
+
+def gifdome_name():
+    if (title := redis.get("title")) is not None:
+        return title.decode()
+    return "GIFdome"
+
+
+def clean_title(title):
+    if not (title := normalize_text(title, 64)):
+        raise ValueError("The title can't be empty.")
+    return title
+
+
+def start_submissions(chat_id, text=None):
+    if text is None:
+        text = f"The {gifdome_name()} has started! Send your me dankest GIFs!"
+    redis.set("state", State.TAKING_SUBMISSIONS.value)
+    redis.set("group_id", chat_id)
+    redis.set("submissions_started_at", now())
+    gif_chat_filter.add_chat_ids(chat_id)
+    dispatcher.add_handler(gif_handler)
+
+    welcome = bot.send_message(chat_id=chat_id, text=text)
+    pin_message(chat_id, welcome.message_id, disable_notification=True)
+    update_chat_description()
+
+
+def check_stale_submissions(context):
+    if redis.get("state") != State.TAKING_SUBMISSIONS.value or redis.get("mode") == b"collect":
+        return
+    if (started_at := redis_get_int("submissions_started_at")) is None:
+        return
+    group_id = redis_get_int("group_id")
+    stale_config = config["stale_submissions"]
+
+    if (nudged_at := redis_get_int("stale_nudged_at")) is None:
+        if now() - started_at < stale_config.get("nudge_after", 7 * 24 * 3600):
+            return
+        grace = duration(stale_config.get("grace_period", 2 * 24 * 3600))
+        bot.send_message(
+            chat_id=group_id,
+            text=(
+                f"This GIFdome has been collecting GIFs for {duration(now() - started_at)}. "
+                f"Start voting with /voting or it will be archived in {grace}."
+            ),
+        )
+        redis.set("stale_nudged_at", now())
+        return
+
+    if now() - nudged_at < stale_config.get("grace_period", 2 * 24 * 3600):
+        return
+    reset()
+    bot.send_message(chat_id=group_id, text="This GIFdome has been archived since voting never started.")
+    notify_admins("A GIFdome was archived after taking submissions for too long.")
+
+
+if config.get("stale_submissions", {}).get("enabled", False):
+    updater.job_queue.run_repeating(check_stale_submissions, interval=3600)
+
+
+def start_next_gifdome(group_id, winner_id):
+    # With carry_over, every submission except the winner's stays in the next GIFdome
+    carry_over = config["rolling"].get("carry_over", False)
+    # The next GIFdome runs with the same /start settings
+    settings = {key: redis.get(key) for key in ["title", "poll_options", "mode"]}
+    reset(delete_submissions=not carry_over)
+    redis.delete("seeding")
+    for key, value in settings.items():
+        if value is not None:
+            redis.set(key, value)
+    if carry_over:
+        with db:
+            with db.cursor() as cur:
+                cur.execute('DELETE FROM "submissions" WHERE "gif_id" = %s', (winner_id,))
+    start_submissions(group_id, text=f"A new {gifdome_name()} has started! Send your me dankest GIFs!")
+
+
 def start_command(update, context):
     if update.effective_user.username not in config["admins"]:
         update.effective_message.reply_text("This bot can be only started by its admins.")
@@ -853,26 +2658,67 @@ def start_command(update, context):
         return
 
     chat_id = update.effective_chat.id
+    if TESTING and chat_id != config["debug"]["test_chat_id"]:
+        update.effective_message.reply_text("In testing mode this bot can be only started in the test chat.")
+        return
     if redis.get("state") != State.NOT_STARTED.value:
         context.bot.send_message(
             chat_id=chat_id,
             text="The GIFdome has already begun!"
         )
         return
-    redis.set("state", State.TAKING_SUBMISSIONS.value)
-    redis.set("group_id", chat_id)
-    gif_chat_filter.add_chat_ids(chat_id)
-    dispatcher.add_handler(gif_handler)
 
-    welcome = context.bot.send_message(
-        chat_id=chat_id,
-        text="The GIFdome has started! Send your me dankest GIFs!",
-    )
-    context.bot.pin_chat_message(
-        chat_id=chat_id,
-        message_id=welcome.message_id,
-        disable_notification=True,
-    )
+    usage = "Usage: /start [like=previous] [mode=tournament|collect] [left=LABEL] [right=LABEL] [title=TITLE]"
+    try:
+        options = parse_command_options(context.args)
+    except ValueError:
+        update.effective_message.reply_text(usage)
+        return
+
+    previous = {}
+    if (like := options.pop("like", None)) is not None:
+        if like != "previous":
+            update.effective_message.reply_text(usage)
+            return
+        previous = json.loads(redis.get("previous_settings") or "{}")
+        if previous.get("group_id") != chat_id:
+            update.effective_message.reply_text(
+                "There is no previous GIFdome in this chat to copy settings from."
+            )
+            return
+
+    mode = options.pop("mode", previous.get("mode", "tournament"))
+    if mode not in ["tournament", "collect"]:
+        update.effective_message.reply_text(usage)
+        return
+    default_labels = previous.get("poll_options", poll_options)
+    labels = [options.pop("left", default_labels[0]), options.pop("right", default_labels[1])]
+    title = options.pop("title", None)
+    if options:
+        update.effective_message.reply_text(usage)
+        return
+    if title is not None:
+        try:
+            title = clean_title(title)
+        except ValueError as e:
+            update.effective_message.reply_text(str(e))
+            return
+    if (error := poll_options_error(labels)) is not None:
+        update.effective_message.reply_text(error)
+        return
+    if labels != poll_options:
+        redis.set("poll_options", json.dumps(labels))
+    if mode == "collect":
+        redis.set("mode", mode)
+    if title is not None:
+        redis.set("title", title)
+
+    start_submissions(chat_id)
+    if previous:
+        copied = f"poll options {labels[0]} and {labels[1]}"
+        if mode == "collect":
+            copied += ", collect-only mode"
+        context.bot.send_message(chat_id=chat_id, text=f"Copied from the previous GIFdome: {copied}.")
 
 
 start_handler = CommandHandler(command="start", callback=start_command)
@@ -892,6 +2738,131 @@ stop_handler = CommandHandler(
 dispatcher.add_handler(stop_handler)
 
 
+class GroupAccessError(Exception):
+    pass
+
+
+def is_admin(user):
+    if user.username in config["admins"]:
+        return True
+    # Group admins can only manage the GIFdome from a DM if that's allowed
+    if not config.get("group_admins", False):
+        return False
+    if (group_id := redis_get_int("group_id")) is None:
+        return False
+    try:
+        member = bot.get_chat_member(chat_id=group_id, user_id=user.id)
+    except Unauthorized as e:
+        # The bot was removed from the group
+        raise GroupAccessError(e.message) from e
+    except BadRequest as e:
+        if e.message in ["Chat not found", "Member list is inaccessible"]:
+            raise GroupAccessError(e.message) from e
+        return False
+    return member.status in ["creator", "administrator"]
+
+
+def status_lines():
+    state = redis.get("state")
+    if state == State.NOT_STARTED.value:
+        return ["The GIFdome has not started."]
+
+    lines = []
+    if (group_id := redis_get_int("group_id")) is not None:
+        lines.append(f"Group: {bot.get_chat(chat_id=group_id).title}")
+
+    if state == State.TAKING_SUBMISSIONS.value:
+        with db:
+            with db.cursor() as cur:
+                cur.execute('SELECT count(DISTINCT "gif_id"), count(DISTINCT "user_id") FROM "submissions"')
+                gif_count, user_count = cur.fetchone()
+        lines.append("Phase: submissions")
+        lines.append(f"{gif_count} GIF{plural(gif_count)} from {user_count} participant{plural(user_count)}")
+    elif state == State.VOTING.value:
+        match_index = redis_get_int("current_match")
+        voter_count = redis_get_int("current_voter_count") or 0
+        lines.append("Phase: voting")
+        lines.append(f"Current vote: {match_index + 1}/255, {voter_count} voter{plural(voter_count)}")
+        if (time_left := poll_time_left()) is not None and time_left > 0:
+            lines.append(f"Can be closed in {duration(time_left)}")
+    elif state == State.ENDED.value:
+        lines.append("Phase: ended")
+    return lines
+
+
+def status_command(update, context):
+    if not is_admin(update.effective_user):
+        update.effective_message.reply_text("Only GIFdome admins can see its status.")
+        return
+    context.bot.send_message(chat_id=update.effective_chat.id, text="\n".join(status_lines()))
+
+
+status_handler = CommandHandler(
+    command="status",
+    callback=status_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(status_handler)
+
+
+def skip_command(update, context):
+    if not is_admin(update.effective_user):
+        update.effective_message.reply_text("Only GIFdome admins can skip polls.")
+        return
+    if redis.get("state") != State.VOTING.value:
+        update.effective_message.reply_text("The GIFdome is not in voting phase.")
+        return
+    if (match := current_match()) is None or match["winner"] is not None:
+        # Between polls, next_match would only reschedule the next one
+        update.effective_message.reply_text("There is no poll open right now.")
+        return
+    next_match()
+    update.effective_message.reply_text("Skipped to the next poll.")
+
+
+skip_handler = CommandHandler(
+    command="skip",
+    callback=skip_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(skip_handler)
+
+
+def abort_command(update, context):
+    if not is_admin(update.effective_user):
+        update.effective_message.reply_text("Only GIFdome admins can abort it.")
+        return
+    if (group_id := redis_get_int("group_id")) is None:
+        update.effective_message.reply_text("The GIFdome has not started.")
+        return
+    reset()
+    bot.send_message(chat_id=group_id, text="The GIFdome has been reset.")
+    update.effective_message.reply_text("The GIFdome has been reset.")
+
+
+abort_handler = CommandHandler(
+    command="abort",
+    callback=abort_command,
+    filters=Filters.chat_type.private,
+)
+dispatcher.add_handler(abort_handler)
+
+
+def error_handler(update, context):
+    if isinstance(context.error, GroupAccessError):
+        logging.warning(f"Cannot check group membership: {context.error}")
+        if update is not None and update.effective_message is not None:
+            update.effective_message.reply_text(
+                "I need to be in the GIFdome group and able to see its members to check that."
+            )
+        return
+    logging.exception(context.error)
+    notify_admins(f"Error while handling an update: {context.error!r}")
+
+
+dispatcher.add_error_handler(error_handler)
+
+
 state = redis.get("state")
 group_id = redis_get_int("group_id")
 
@@ -940,14 +2911,86 @@ if state == State.VOTING.value:
         if redis.get(key) is None:
             raise ValueError(f"Missing {key} in Redis")
 
+def fetch_poll(chat_id, message_id):
+    # The Bot API can't read a poll directly, but a forwarded copy of it
+    # carries the current counts
+    for admin_chat_id in config.get("admin_chat_ids", []):
+        try:
+            forwarded = bot.forward_message(
+                chat_id=admin_chat_id,
+                from_chat_id=chat_id,
+                message_id=message_id,
+                disable_notification=True,
+            )
+        except TelegramError as e:
+            logging.warning(f"Could not forward poll to {admin_chat_id}: {e.message}")
+            continue
+        try:
+            bot.delete_message(chat_id=admin_chat_id, message_id=forwarded.message_id)
+        except TelegramError as e:
+            logging.warning(f"Could not delete forwarded poll in {admin_chat_id}: {e.message}")
+        return forwarded.poll
+    return None
+
+
+def restore_current_poll():
+    if (match := current_match()) is not None and match["winner"] is not None:
+        logging.warning("Current match has a winner but the next poll was not started")
+        next_match()
+        return
+
+    poll_message_id = redis_get_int("current_poll_message")
+    if (
+        poll_message_id is not None
+        and redis.get("current_poll") is not None
+        and redis_get_int("current_poll_start") is not None
+    ):
+        if TESTING:
+            return
+        # Re-pinning is harmless and tells us whether the poll message still exists
+        try:
+            bot.pin_chat_message(
+                chat_id=group_id,
+                message_id=poll_message_id,
+                disable_notification=True,
+            )
+        except BadRequest as e:
+            if e.message != "Message to pin not found":
+                raise e
+        else:
+            # Votes cast while the bot was down may be enough to close the poll
+            if (poll := fetch_poll(group_id, poll_message_id)) is not None:
+                process_poll(poll)
+            else:
+                logging.warning("Could not fetch the current poll, waiting for the next poll update")
+            return
+
+    logging.warning("Current poll is missing, sending it again")
+    notify_admins("The current poll was missing after a restart and is being sent again.")
+    match_index = redis_get_int("current_match")
+    matches = json.loads(redis.get("matches"))
+    new_poll(
+        match_participants(match_index, matches),
+        matches[match_index]["duration"],
+        started_at=redis_get_int("current_poll_start"),
+    )
+
+
+drain_outbox()
 update_bracket_image()
 update_chat_description()
 
 if config["downtime_notifications"] and group_id is not None:
     bot.send_message(chat_id=group_id, text="The GIFdome is back up! Sorry for the downtime.")
 
-updater.start_webhook(
-    listen="127.0.0.1",
-    port=config["webhook_port"],
-    webhook_url=config["webhook_url"],
-)
+if state == State.VOTING.value:
+    restore_current_poll()
+
+if TESTING or update_mode == "polling":
+    updater.start_polling()
+else:
+    updater.start_webhook(
+        listen="127.0.0.1",
+        port=config["webhook_port"],
+        webhook_url=config["webhook_url"],
+    )
@@ -0,0 +1,72 @@
+import logging
+import threading
+
+import psycopg2
+from psycopg2.errors import SerializationFailure
+from psycopg2.extensions import TRANSACTION_STATUS_IDLE
+
+
+class ThreadConnection(threading.local):
+    # Handlers and jobs run on several threads; each gets its own connection so
+    # one thread's transaction (or isolation level) never leaks into another's
+    all_connections = []
+    all_connections_lock = threading.Lock()
+
+    def __init__(self, dsn):
+        self.dsn = dsn
+        self.connection = None
+
+    def get(self):
+        if self.connection is None or self.connection.closed:
+            self.connection = psycopg2.connect(self.dsn)
+            with self.all_connections_lock:
+                self.all_connections.append(self.connection)
+        return self.connection
+
+    def transaction_open(self):
+        # Checked without connecting, since a thread without a connection has
+        # no transaction either
+        return (
+            self.connection is not None
+            and not self.connection.closed
+            and self.connection.get_transaction_status() != TRANSACTION_STATUS_IDLE
+        )
+
+    def close(self):
+        with self.all_connections_lock:
+            for connection in self.all_connections:
+                connection.close()
+            self.all_connections.clear()
+
+    @property
+    def isolation_level(self):
+        return self.get().isolation_level
+
+    @isolation_level.setter
+    def isolation_level(self, value):
+        self.get().isolation_level = value
+
+    def __enter__(self):
+        return self.get().__enter__()
+
+    def __exit__(self, *exc_info):
+        return self.get().__exit__(*exc_info)
+
+    def __getattr__(self, name):
+        return getattr(self.get(), name)
+
+
+def in_transaction(db, func, *, isolation_level=None, attempts=3):
+    # func may run several times, so it must only touch the database; anything
+    # else (replies, downloads) belongs in what it returns for after the commit
+    for attempt in range(1, attempts + 1):
+        db.isolation_level = isolation_level
+        try:
+            with db:
+                return func()
+        except SerializationFailure:
+            if attempt == attempts:
+                raise
+            logging.info(f"Serialization failure, retrying (attempt {attempt}/{attempts})")
+        finally:
+            db.isolation_level = None
@@ -0,0 +1,636 @@
+# Stand-ins for Telegram, Redis and Postgres so tests can load the bot without
+# them. Importing this module installs the fake packages; load_bot() then runs
+# __main__.py against a fresh fake Redis and a SQLite database per test.
+
+import copy
+import fnmatch
+import importlib.util
+import itertools
+import os
+import shutil
+import sqlite3
+import sys
+import tempfile
+import threading
+import types
+from datetime import datetime, timezone
+from pathlib import Path
+from types import SimpleNamespace
+from unittest import mock
+
+
+def install_module(name, **attributes):
+    module = types.ModuleType(name)
+    module.__dict__.update(attributes)
+    sys.modules[name] = module
+    if "." in name:
+        parent, _, child = name.rpartition(".")
+        setattr(sys.modules[parent], child, module)
+    return module
+
+
+# Shared with load_bot(), which points them at the current test's services
+current = SimpleNamespace(config=None, database_path=None, redis=None)
+
+
+# psycopg2
+
+class SerializationFailure(Exception):
+    pass
+
+
+ISOLATION_LEVEL_SERIALIZABLE = 4
+TRANSACTION_STATUS_IDLE = 0
+TRANSACTION_STATUS_INTRANS = 2
+
+commit_failures = 0
+commit_failures_lock = threading.Lock()
+
+
+def fail_commits(count):
+    # The next count commits, on any connection, fail with a serialization failure
+    global commit_failures
+    with commit_failures_lock:
+        commit_failures = count
+
+
+def take_commit_failure():
+    global commit_failures
+    with commit_failures_lock:
+        if commit_failures == 0:
+            return False
+        commit_failures -= 1
+        return True
+
+
+class Cursor:
+    def __init__(self, connection):
+        self.connection = connection
+        self.rows = []
+        self.rowcount = -1
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        pass
+
+    def execute(self, query, params=None):
+        if params is None and query.strip().rstrip(";").count(";"):
+            self.execute_script(query)
+            return
+        self.connection.begin()
+        if params is not None:
+            query = query.replace("%s", "?")
+            params = [param.isoformat() if isinstance(param, datetime) else param for param in params]
+        cursor = self.connection.sqlite.execute(query, params or ())
+        if cursor.description is not None:
+            self.rows = cursor.fetchall()
+            self.rowcount = len(self.rows)
+        else:
+            self.rows = []
+            self.rowcount = cursor.rowcount
+
+    def execute_script(self, script):
+        # schema.sql, minus what SQLite doesn't support
+        for statement in script.split(";"):
+            lines = [line for line in statement.splitlines() if not line.strip().startswith("--")]
+            statement = "\n".join(lines).strip()
+            if not statement or "ALTER COLUMN" in statement:
+                continue
+            statement = statement.replace("ADD COLUMN IF NOT EXISTS", "ADD COLUMN")
+            try:
+                self.connection.sqlite.execute(statement)
+            except sqlite3.OperationalError as e:
+                if "duplicate column" not in str(e):
+                    raise
+
+    def fetchone(self):
+        return self.rows.pop(0) if self.rows else None
+
+    def fetchall(self):
+        rows, self.rows = self.rows, []
+        return rows
+
+    def __iter__(self):
+        return iter(self.fetchall())
+
+
+class Connection:
+    # Every transaction takes SQLite's write lock up front, so concurrent
+    # transactions run one after another like serializable ones that succeed
+    def __init__(self, path):
+        self.sqlite = sqlite3.connect(path, timeout=30, isolation_level=None, check_same_thread=False)
+        self.sqlite.execute("PRAGMA foreign_keys = ON")
+        self.isolation_level = None
+        self.closed = False
+        self.in_transaction = False
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        if exc_type is None:
+            self.commit()
+        else:
+            self.rollback()
+
+    def cursor(self):
+        return Cursor(self)
+
+    def begin(self):
+        if not self.in_transaction:
+            self.sqlite.execute("BEGIN IMMEDIATE")
+            self.in_transaction = True
+
+    def commit(self):
+        if not self.in_transaction:
+            return
+        if take_commit_failure():
+            self.rollback()
+            raise SerializationFailure("could not serialize access due to concurrent update")
+        self.sqlite.execute("COMMIT")
+        self.in_transaction = False
+
+    def rollback(self):
+        if self.in_transaction:
+            self.sqlite.execute("ROLLBACK")
+            self.in_transaction = False
+
+    def get_transaction_status(self):
+        return TRANSACTION_STATUS_INTRANS if self.in_transaction else TRANSACTION_STATUS_IDLE
+
+    def close(self):
+        if not self.closed:
+            self.sqlite.close()
+            self.closed = True
+
+
+def connect(dsn):
+    return Connection(current.database_path)
+
+
+install_module("psycopg2", connect=connect)
+install_module("psycopg2.errors", SerializationFailure=SerializationFailure)
+install_module(
+    "psycopg2.extensions",
+    ISOLATION_LEVEL_SERIALIZABLE=ISOLATION_LEVEL_SERIALIZABLE,
+    TRANSACTION_STATUS_IDLE=TRANSACTION_STATUS_IDLE,
+    TRANSACTION_STATUS_INTRANS=TRANSACTION_STATUS_INTRANS,
+)
+
+
+# Redis
+
+def encode(value):
+    if isinstance(value, bytes):
+        return value
+    return str(value).encode()
+
+
+class Pipeline:
+    def __init__(self, redis):
+        self.redis = redis
+        self.commands = []
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        pass
+
+    def __getattr__(self, name):
+        def command(*args, **kwargs):
+            self.commands.append((name, args, kwargs))
+        return command
+
+    def execute(self):
+        commands, self.commands = self.commands, []
+        return [getattr(self.redis, name)(*args, **kwargs) for name, args, kwargs in commands]
+
+
+class Redis:
+    def __init__(self, data=None):
+        self.data = {key: encode(value) for key, value in (data or {}).items()}
+        self.lock = threading.RLock()
+
+    def get(self, key):
+        value = self.data.get(key)
+        return value if isinstance(value, bytes) else None
+
+    def set(self, key, value, nx=False, ex=None):
+        with self.lock:
+            if nx and key in self.data:
+                return None
+            self.data[key] = encode(value)
+            return True
+
+    def delete(self, *keys):
+        with self.lock:
+            return sum(self.data.pop(key, None) is not None for key in keys)
+
+    def rpush(self, key, *values):
+        with self.lock:
+            items = self.data.setdefault(key, [])
+            items.extend(encode(value) for value in values)
+            return len(items)
+
+    def lindex(self, key, index):
+        try:
+            return self.data.get(key, [])[index]
+        except IndexError:
+            return None
+
+    def lpop(self, key):
+        with self.lock:
+            items = self.data.get(key)
+            if not items:
+                return None
+            item = items.pop(0)
+            if not items:
+                del self.data[key]
+            return item
+
+    def sadd(self, key, *values):
+        with self.lock:
+            members = self.data.setdefault(key, set())
+            added = {encode(value) for value in values} - members
+            members |= added
+            return len(added)
+
+    def srem(self, key, *values):
+        with self.lock:
+            members = self.data.get(key, set())
+            removed = {encode(value) for value in values} & members
+            members -= removed
+            return len(removed)
+
+    def scard(self, key):
+        return len(self.data.get(key, set()))
+
+    def scan_iter(self, pattern="*"):
+        return [key for key in list(self.data) if fnmatch.fnmatchcase(key, pattern)]
+
+    def pipeline(self):
+        return Pipeline(self)
+
+    def close(self):
+        pass
+
+
+install_module("redis", Redis=lambda *args, **kwargs: current.redis)
+
+
+# python-telegram-bot
+
+class TelegramError(Exception):
+    def __init__(self, message):
+        super().__init__(message)
+        self.message = message
+
+
+class BadRequest(TelegramError):
+    pass
+
+
+class Unauthorized(TelegramError):
+    pass
+
+
+class RetryAfter(TelegramError):
+    def __init__(self, retry_after):
+        super().__init__(f"Flood control exceeded. Retry in {retry_after} seconds")
+        self.retry_after = retry_after
+
+
+class Bot:
+    # Records every call in self.calls; self.responses maps a method name to
+    # a function that replaces its default response (or raises)
+    def __init__(self, token=None, base_url=None, base_file_url=None, request=None):
+        self.token = token
+        self.base_url = base_url
+        self.username = "gifdome_bot"
+        self.calls = []
+        self.responses = {}
+        self.message_ids = itertools.count(1000)
+        self.request = SimpleNamespace(post=lambda url, data: self.call("post", {"url": url, "data": data}))
+
+    def call(self, method, kwargs):
+        self.calls.append((method, kwargs))
+        if method in self.responses:
+            return self.responses[method](**kwargs)
+        message_id = next(self.message_ids)
+        return SimpleNamespace(message_id=message_id, poll=SimpleNamespace(id=f"poll{message_id}"))
+
+    def calls_to(self, method):
+        return [kwargs for name, kwargs in self.calls if name == method]
+
+    def send_animation(self, **kwargs):
+        return self.call("send_animation", kwargs)
+
+    def send_message(self, **kwargs):
+        return self.call("send_message", kwargs)
+
+    def send_photo(self, **kwargs):
+        return self.call("send_photo", kwargs)
+
+    def send_poll(self, **kwargs):
+        return self.call("send_poll", kwargs)
+
+    def send_sticker(self, **kwargs):
+        return self.call("send_sticker", kwargs)
+
+    def get_chat(self, chat_id):
+        self.calls.append(("get_chat", {"chat_id": chat_id}))
+        return SimpleNamespace(id=chat_id, title="GIFdome", username=None)
+
+    def get_chat_member(self, **kwargs):
+        if "get_chat_member" in self.responses:
+            return self.call("get_chat_member", kwargs)
+        self.calls.append(("get_chat_member", kwargs))
+        return SimpleNamespace(status="member")
+
+    def __getattr__(self, name):
+        if name.startswith("_"):
+            raise AttributeError(name)
+        return lambda **kwargs: self.call(name, kwargs)
+
+
+class Handler:
+    def __init__(self, **kwargs):
+        self.__dict__.update(kwargs)
+
+
+class DispatcherHandlerStop(Exception):
+    pass
+
+
+class Dispatcher:
+    def __init__(self):
+        self.handlers = []
+        self.error_handlers = []
+
+    def add_handler(self, handler, group=0):
+        self.handlers.append((group, handler))
+
+    def add_error_handler(self, callback):
+        self.error_handlers.append(callback)
+
+
+class JobQueue:
+    def __init__(self):
+        self.jobs = []
+
+    def run_repeating(self, callback, interval, **kwargs):
+        self.jobs.append(("repeating", callback, interval))
+
+    def run_once(self, callback, when, **kwargs):
+        self.jobs.append(("once", callback, when))
+
+
+class Updater:
+    def __init__(self, bot=None, **kwargs):
+        self.bot = bot
+        self.dispatcher = Dispatcher()
+        self.job_queue = JobQueue()
+
+    def start_polling(self, **kwargs):
+        pass
+
+    def start_webhook(self, **kwargs):
+        pass
+
+
+class Filter:
+    def __and__(self, other):
+        return Filter()
+
+
+class ChatFilter(Filter):
+    def __init__(self):
+        self.chat_ids = set()
+
+    def add_chat_ids(self, chat_id):
+        self.chat_ids |= set(chat_id if isinstance(chat_id, list) else [chat_id])
+
+    def remove_chat_ids(self, chat_id):
+        self.chat_ids -= set(chat_id if isinstance(chat_id, list) else [chat_id])
+
+
+class Filters:
+    animation = Filter()
+    command = Filter()
+    chat_type = SimpleNamespace(private=Filter(), groups=Filter())
+
+    @staticmethod
+    def chat():
+        return ChatFilter()
+
+    @staticmethod
+    def user(username=None):
+        return Filter()
+
+
+install_module("telegram", Bot=Bot, Update=type("Update", (), {}))
+install_module("telegram.constants", PARSEMODE_MARKDOWN_V2="MarkdownV2")
+install_module(
+    "telegram.error",
+    BadRequest=BadRequest,
+    RetryAfter=RetryAfter,
+    TelegramError=TelegramError,
+    Unauthorized=Unauthorized,
+)
+install_module(
+    "telegram.ext",
+    CommandHandler=Handler,
+    DispatcherHandlerStop=DispatcherHandlerStop,
+    MessageHandler=Handler,
+    PollHandler=Handler,
+    TypeHandler=Handler,
+    Updater=Updater,
+)
+install_module("telegram.ext.filters", Filters=Filters)
+install_module("telegram.utils")
+install_module("telegram.utils.request", Request=Handler)
+
+
+# Pillow and toml
+
+def open_image(path, *args, **kwargs):
+    raise OSError(f"cannot identify image file {str(path)!r}")
+
+
+install_module("PIL")
+install_module("PIL.Image", LANCZOS=1, open=open_image)
+install_module("PIL.ImageOps")
+install_module("toml", load=lambda path: copy.deepcopy(current.config))
+
+
+# Loading the bot
+
+bot_dir = Path(__file__).parent
+group_id = -1001234567890
+
+default_config = {
+    "debug": {"enabled": False, "rng_seed": 0},
+    "api_token": "123:test",
+    "redis_socket": "/run/redis/redis.sock",
+    "redis_db": 0,
+    "admins": ["gif_admin"],
+    "min_votes": 1,
+    "max_submissions": 10,
+    "downtime_notifications": False,
+    "webhook_port": 8443,
+    "webhook_url": "https://example.com/webhook",
+    "rate_limit": {"enabled": False},
+}
+
+
+def load_bot(test, config=None, redis_data=None):
+    # Runs a fresh copy of __main__.py with config merged over default_config;
+    # redis_data defaults to a GIFdome that hasn't started
+    project_dir = tempfile.TemporaryDirectory()
+    test.addCleanup(project_dir.cleanup)
+    project_path = Path(project_dir.name)
+    shutil.copy(bot_dir.parent / "schema.sql", project_path / "schema.sql")
+    (project_path / "gifs").mkdir()
+
+    current.config = {**copy.deepcopy(default_config), **(config or {})}
+    current.config["log_file"] = str(project_path / "bot.log")
+    current.database_path = project_path / "gifdome.sqlite3"
+    current.redis = Redis(redis_data or {"state": b"not-started"})
+
+    spec = importlib.util.spec_from_file_location("gifdome", bot_dir / "__main__.py")
+    gifdome = importlib.util.module_from_spec(spec)
+    with mock.patch.dict(os.environ, {"GIFDOME_DIR": str(project_path)}), mock.patch("signal.signal"):
+        spec.loader.exec_module(gifdome)
+    test.addCleanup(gifdome.db.close)
+    gifdome.bot.calls.clear()
+    return gifdome
+
+
+def taking_submissions():
+    return {"state": b"taking-submissions", "group_id": group_id}
+
+
+def user(user_id, username=None):
+    return SimpleNamespace(
+        id=user_id,
+        username=username or f"gif_fan_{user_id}",
+        full_name=f"GIF Fan {user_id}",
+        is_bot=False,
+    )
+
+
+def group_chat():
+    return SimpleNamespace(id=group_id, type="supergroup", username=None)
+
+
+def private_chat(user):
+    return SimpleNamespace(id=user.id, type="private", username=user.username)
+
+
+class Animation:
+    def __init__(self, file_unique_id, *, file_name=None, mime_type="video/mp4"):
+        self.file_unique_id = file_unique_id
+        self.file_id = f"file-{file_unique_id}"
+        self.file_name = file_name
+        self.file_size = 1000
+        self.mime_type = mime_type
+        self.width = 320
+        self.height = 240
+        self.duration = 3
+        self.downloads = 0
+
+    def get_file(self):
+        def download(out):
+            self.downloads += 1
+            out.write(b"GIF89a")
+        return SimpleNamespace(file_path=f"animations/{self.file_id}", download=download)
+
+
+class Message:
+    message_ids = itertools.count(1)
+
+    def __init__(self, chat, from_user, *, text=None, animation=None, caption=None, reply_to_message=None):
+        self.chat = chat
+        self.from_user = from_user
+        self.message_id = next(self.message_ids)
+        self.text = text
+        self.animation = animation
+        self.caption = caption
+        self.reply_to_message = reply_to_message
+        self.forward_date = None
+        self.forward_from = None
+        self.replies = []
+
+    def reply_text(self, text, **kwargs):
+        self.replies.append(text)
+        return SimpleNamespace(message_id=next(self.message_ids))
+
+
+def update(message):
+    return SimpleNamespace(
+        message=message,
+        effective_message=message,
+        effective_user=message.from_user,
+        effective_chat=message.chat,
+    )
+
+
+def dispatch(gifdome, callback, message, args=()):
+    # Calls a handler the way the dispatcher does, errors included
+    context = SimpleNamespace(bot=gifdome.bot, args=list(args), error=None)
+    try:
+        callback(update(message), context)
+    except Exception as e:
+        context.error = e
+        for error_handler in gifdome.dispatcher.error_handlers:
+            error_handler(update(message), context)
+    return message.replies
+
+
+# For setting up and inspecting the database of a loaded bot
+def execute(gifdome, query, params=()):
+    with gifdome.db:
+        with gifdome.db.cursor() as cur:
+            cur.execute(query, params)
+            if query.lstrip().startswith("SELECT"):
+                return cur.fetchall()
+
+
+def add_gif(gifdome, gif_id, *, blocked=False):
+    execute(
+        gifdome,
+        """
+        INSERT INTO "gifs"("id", "file_id", "mime_type", "width", "height", "duration")
+            VALUES (%s, %s, 'video/mp4', 320, 240, 3)
+        """,
+        (gif_id, f"file-{gif_id}"),
+    )
+    if blocked:
+        block_gif(gifdome, gif_id)
+
+
+def block_gif(gifdome, gif_id):
+    execute(
+        gifdome,
+        'INSERT INTO "blocked_gifs"("gif_id", "created") VALUES (%s, %s)',
+        (gif_id, datetime.now(timezone.utc)),
+    )
+
+
+def add_submission(gifdome, user_id, gif_id, submitted_gif_id=None):
+    execute(
+        gifdome,
+        'INSERT INTO "users"("id", "username") VALUES (%s, %s) ON CONFLICT DO NOTHING',
+        (user_id, f"gif_fan_{user_id}"),
+    )
+    execute(
+        gifdome,
+        """
+        INSERT INTO "submissions"("user_id", "gif_id", "created", "submitted_gif_id")
+            VALUES (%s, %s, %s, %s)
+        """,
+        (user_id, gif_id, datetime.now(timezone.utc), submitted_gif_id or gif_id),
+    )
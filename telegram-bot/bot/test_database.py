@@ -0,0 +1,106 @@
+import threading
+import unittest
+
+import fakes
+from database import in_transaction
+from psycopg2.errors import SerializationFailure
+
+
+class FakeConnection:
+    def __init__(self, failures):
+        self.failures = failures
+        self.isolation_level = None
+        self.commits = 0
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        if exc_type is None:
+            if self.failures:
+                self.failures -= 1
+                raise SerializationFailure()
+            self.commits += 1
+
+
+class InTransactionTest(unittest.TestCase):
+    def test_retries_at_the_isolation_level(self):
+        db = FakeConnection(failures=1)
+        runs = []
+
+        def func():
+            runs.append(db.isolation_level)
+            return len(runs)
+
+        self.assertEqual(in_transaction(db, func, isolation_level="serializable"), 2)
+        self.assertEqual(runs, ["serializable", "serializable"])
+        self.assertEqual(db.commits, 1)
+        self.assertIsNone(db.isolation_level)
+
+    def test_gives_up_after_attempts(self):
+        db = FakeConnection(failures=3)
+        with self.assertRaises(SerializationFailure):
+            in_transaction(db, lambda: None, attempts=3)
+        self.assertEqual(db.commits, 0)
+
+
+class SubmissionTransactionTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, redis_data=fakes.taking_submissions())
+        self.addCleanup(fakes.fail_commits, 0)
+
+    def submit(self, user, gif):
+        message = fakes.Message(fakes.group_chat(), user, animation=gif)
+        return fakes.dispatch(self.gifdome, self.gifdome.gif_message, message)
+
+    def submissions(self):
+        with self.gifdome.db:
+            with self.gifdome.db.cursor() as cur:
+                cur.execute('SELECT "user_id", "gif_id" FROM "submissions" ORDER BY "user_id"')
+                return cur.fetchall()
+
+    def test_reply_sent_once_after_serialization_failure(self):
+        gif = fakes.Animation("gif1")
+        fakes.fail_commits(1)
+        replies = self.submit(fakes.user(1), gif)
+
+        self.assertEqual(replies, ["Thanks for the new GIF! You have submitted 1/10 GIFs."])
+        self.assertEqual(self.submissions(), [(1, "gif1")])
+        self.assertEqual(gif.downloads, 1)
+
+    def test_reply_after_giving_up(self):
+        fakes.fail_commits(3)
+        replies = self.submit(fakes.user(1), fakes.Animation("gif1"))
+
+        self.assertEqual(replies, ["Welp! Something went wrong when trying to process your submission."])
+        self.assertEqual(self.submissions(), [])
+
+    def test_concurrent_submissions_are_not_lost(self):
+        users = [fakes.user(user_id) for user_id in range(1, 9)]
+        replies = {}
+        barrier = threading.Barrier(len(users))
+
+        def submit(user):
+            barrier.wait()
+            replies[user.id] = self.submit(user, fakes.Animation("gif1"))
+
+        # A failed commit is retried, but at most twice per submission
+        fakes.fail_commits(2)
+        threads = [threading.Thread(target=submit, args=(user,)) for user in users]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+
+        self.assertEqual(self.submissions(), [(user.id, "gif1") for user in users])
+        self.assertEqual(
+            sorted(reply for user_replies in replies.values() for reply in user_replies),
+            sorted(
+                ["Thanks for the new GIF! You have submitted 1/10 GIFs."]
+                + [f"Got it! This GIF has been submitted {count} times." for count in range(2, 9)]
+            ),
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()
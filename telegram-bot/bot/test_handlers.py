@@ -0,0 +1,252 @@
+import json
+import unittest
+from types import SimpleNamespace
+from unittest import mock
+
+import fakes
+
+
+class BlockedGifTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, redis_data=fakes.taking_submissions())
+        self.gif_path = self.gifdome.project_path / "gifs" / "file-gif1.mp4"
+
+    def submit(self, user, gif):
+        message = fakes.Message(fakes.group_chat(), user, animation=gif)
+        return fakes.dispatch(self.gifdome, self.gifdome.gif_message, message)
+
+    def test_blocked_gif_is_not_downloaded(self):
+        fakes.add_gif(self.gifdome, "gif1", blocked=True)
+        gif = fakes.Animation("gif1")
+
+        self.assertEqual(self.submit(fakes.user(1), gif), ["This GIF has been blocked."])
+        self.assertEqual(gif.downloads, 0)
+        self.assertFalse(self.gif_path.exists())
+
+    def test_file_downloaded_before_block_is_removed(self):
+        self.submit(fakes.user(1), fakes.Animation("gif1"))
+        self.assertTrue(self.gif_path.exists())
+        fakes.block_gif(self.gifdome, "gif1")
+
+        self.assertEqual(self.submit(fakes.user(2), fakes.Animation("gif1")), ["This GIF has been blocked."])
+        self.assertFalse(self.gif_path.exists())
+        self.assertEqual(fakes.execute(self.gifdome, 'SELECT "user_id" FROM "submissions"'), [(1,)])
+
+
+class MergedDuplicatesTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, {"seeding": "recency"}, fakes.taking_submissions())
+        for i in range(255):
+            fakes.add_gif(self.gifdome, f"gif{i}")
+            fakes.add_submission(self.gifdome, 1, f"gif{i}")
+        fakes.add_gif(self.gifdome, "duplicate")
+        fakes.execute(
+            self.gifdome,
+            'INSERT INTO "duplicates"("duplicate_id", "primary_id") VALUES (%s, %s)',
+            ("duplicate", "gif0"),
+        )
+
+    def start_voting(self):
+        message = fakes.Message(fakes.private_chat(fakes.user(99, "gif_admin")), fakes.user(99, "gif_admin"))
+        fakes.dispatch(self.gifdome, self.gifdome.voting_command, message)
+        return [call["text"] for call in self.gifdome.bot.calls_to("send_message")]
+
+    def test_submitted_duplicate_is_mentioned(self):
+        fakes.add_submission(self.gifdome, 2, "gif0", "duplicate")
+        self.assertEqual(
+            self.start_voting(),
+            ["Not enough distinct GIFs: 256 are needed, but only 255 are left after merging 1 duplicate."],
+        )
+
+    def test_unsubmitted_duplicate_is_not_counted(self):
+        fakes.add_submission(self.gifdome, 2, "gif0")
+        self.assertEqual(self.start_voting(), ["Not enough submissions: 256 GIFs are needed, got 255."])
+
+    def test_too_few_even_without_merging(self):
+        fakes.execute(self.gifdome, 'DELETE FROM "submissions" WHERE "gif_id" = %s', ("gif1",))
+        fakes.add_submission(self.gifdome, 2, "gif0", "duplicate")
+        self.assertEqual(self.start_voting(), ["Not enough submissions: 256 GIFs are needed, got 254."])
+
+
+class NextMatchTest(unittest.TestCase):
+    def setUp(self):
+        gif_ids = [f"gif{i}" for i in range(256)]
+        self.gifdome = fakes.load_bot(self, redis_data=fakes.taking_submissions())
+        self.gifdome.redis.set("state", self.gifdome.State.VOTING.value)
+        self.gifdome.redis.set("seeding", json.dumps(gif_ids))
+        for gif_id in gif_ids:
+            fakes.add_gif(self.gifdome, gif_id)
+        self.bot = self.gifdome.bot
+        self.bot.responses["stop_poll"] = lambda **kwargs: SimpleNamespace(
+            options=[SimpleNamespace(voter_count=3), SimpleNamespace(voter_count=1)],
+        )
+
+    def pin_calls(self):
+        return [
+            (name, kwargs["message_id"]) for name, kwargs in self.bot.calls
+            if name in ["pin_chat_message", "unpin_chat_message"]
+        ]
+
+    def test_previous_poll_unpinned_before_next_is_pinned(self):
+        self.gifdome.next_match()
+        first_poll = self.gifdome.redis_get_int("current_poll_message")
+        self.bot.calls.clear()
+
+        self.gifdome.next_match()
+        second_poll = self.gifdome.redis_get_int("current_poll_message")
+        self.assertNotEqual(first_poll, second_poll)
+        self.assertEqual(self.pin_calls(), [("unpin_chat_message", first_poll), ("pin_chat_message", second_poll)])
+
+
+class GroupAdminTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(
+            self,
+            {"group_admins": True, "admin_chat_ids": [42]},
+            fakes.taking_submissions(),
+        )
+        self.bot = self.gifdome.bot
+        self.user = fakes.user(7, "group_admin")
+
+    def status(self, status=None, error=None):
+        def get_chat_member(**kwargs):
+            if error is not None:
+                raise error
+            return SimpleNamespace(status=status)
+
+        self.bot.responses["get_chat_member"] = get_chat_member
+        message = fakes.Message(fakes.private_chat(self.user), self.user, text="/status")
+        return fakes.dispatch(self.gifdome, self.gifdome.status_command, message)
+
+    def admin_notifications(self):
+        return [call for call in self.bot.calls_to("send_message") if call["chat_id"] == 42]
+
+    def test_group_admin_sees_status(self):
+        self.assertEqual(self.status("administrator"), [])
+        self.assertEqual(self.bot.calls_to("send_message")[-1]["chat_id"], self.user.id)
+
+    def test_not_a_member_is_rejected(self):
+        for status in ["member", "left", "kicked"]:
+            with self.subTest(status=status):
+                self.assertEqual(self.status(status), ["Only GIFdome admins can see its status."])
+        # Telegram's answer for someone who was never in the group
+        self.assertEqual(
+            self.status(error=fakes.BadRequest("User not found")),
+            ["Only GIFdome admins can see its status."],
+        )
+        self.assertEqual(self.admin_notifications(), [])
+
+    def test_lost_group_access_is_explained(self):
+        for error in [fakes.Unauthorized("Forbidden: bot was kicked"), fakes.BadRequest("Chat not found")]:
+            with self.subTest(error=error.message):
+                self.assertEqual(
+                    self.status(error=error),
+                    ["I need to be in the GIFdome group and able to see its members to check that."],
+                )
+        self.assertEqual(self.admin_notifications(), [])
+
+
+class FinalTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, redis_data=fakes.taking_submissions())
+        self.gifdome.redis.set("state", self.gifdome.State.VOTING.value)
+        fakes.add_gif(self.gifdome, "gif0")
+
+    def final_caption(self):
+        matches = self.gifdome.generate_matches()
+        matches[-1]["winner"] = "gif0"
+        with mock.patch.object(self.gifdome, "send_bracket") as send_bracket:
+            self.gifdome.record_winner(matches, len(matches) - 1)
+        return send_bracket.call_args.kwargs["caption"]
+
+    def test_names_the_gifdome(self):
+        self.assertTrue(self.final_caption().startswith("GIFdome on ohi\\!"))
+
+    def test_names_a_themed_gifdome(self):
+        self.gifdome.redis.set("title", "Halloween GIFdome 2.0")
+        self.assertTrue(self.final_caption().startswith("Halloween GIFdome 2\\.0 on ohi\\!"))
+
+
+class TotalDurationTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, {"max_total_duration": 3600}, fakes.taking_submissions())
+        self.gifdome.redis.set("state", self.gifdome.State.VOTING.value)
+        self.gifdome.redis.set("seeding", json.dumps([f"gif{i}" for i in range(256)]))
+        self.gifdome.redis.set("submissions_started_at", self.gifdome.now() - 7200)
+        for i in range(2):
+            fakes.add_gif(self.gifdome, f"gif{i}")
+        self.gifdome.next_match()
+        self.bot = self.gifdome.bot
+        self.bot.calls.clear()
+
+    def test_failed_attempt_is_not_repeated_every_check(self):
+        def stop_poll(**kwargs):
+            raise fakes.BadRequest("Poll has already been closed")
+
+        self.bot.responses["stop_poll"] = stop_poll
+        for _ in range(3):
+            self.gifdome.check_total_duration(None)
+        self.assertEqual(
+            [call["text"] for call in self.bot.calls_to("send_message")],
+            ["Oopsie! This requires some manual attention."],
+        )
+
+
+class ConfigValidationTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self)
+
+    def validate(self, **config):
+        self.gifdome.validate_config({**fakes.default_config, **config})
+
+    def test_round_durations_at_most_a_week(self):
+        week = 7 * 24 * 3600
+        self.validate(round_durations=[1800] * 7 + [week])
+        with self.assertRaises(ValueError):
+            self.validate(round_durations=[1800] * 7 + [week + 1])
+
+    def test_min_margin(self):
+        for min_margin in [0, 3, "0%", "10%", "12.5%", "100%"]:
+            with self.subTest(min_margin=min_margin):
+                self.validate(min_margin=min_margin)
+        for min_margin in [-1, 2.5, True, "3", "%", "ten%", "-5%", "101%", "nan%"]:
+            with self.subTest(min_margin=min_margin):
+                with self.assertRaises(ValueError):
+                    self.validate(min_margin=min_margin)
+
+
+class ExtendTest(unittest.TestCase):
+    def setUp(self):
+        self.gifdome = fakes.load_bot(self, {"max_match_duration": 7200}, fakes.taking_submissions())
+        self.gifdome.redis.set("state", self.gifdome.State.VOTING.value)
+        self.gifdome.redis.set("seeding", json.dumps([f"gif{i}" for i in range(256)]))
+        for i in range(2):
+            fakes.add_gif(self.gifdome, f"gif{i}")
+        self.gifdome.next_match()
+        self.gifdome.bot.calls.clear()
+
+    def duration(self):
+        return json.loads(self.gifdome.redis.get("matches"))[0]["duration"]
+
+    def extend(self, seconds):
+        admin = fakes.user(99, "gif_admin")
+        message = fakes.Message(fakes.group_chat(), admin, text=f"/extend {seconds}")
+        fakes.dispatch(self.gifdome, self.gifdome.extend_command, message, [str(seconds)])
+        return self.gifdome.bot.calls_to("send_message")[-1]["text"]
+
+    def test_extension_stops_at_max_match_duration(self):
+        self.assertEqual(self.duration(), 1800)
+        self.assertTrue(self.extend(10000).startswith("Poll extended by 1 hour 30 minutes."))
+        self.assertEqual(self.duration(), 7200)
+        self.assertEqual(self.extend(60), "This poll can\u2019t be extended, it\u2019s already 2 hours long.")
+        self.assertEqual(self.duration(), 7200)
+
+    def test_margin_extension_stops_at_max_match_duration(self):
+        self.gifdome.config["margin_extension"] = 6000
+        self.assertTrue(self.gifdome.extend_for_margin())
+        self.assertEqual(self.duration(), 7200)
+        self.assertFalse(self.gifdome.extend_for_margin())
+
+
+if __name__ == "__main__":
+    unittest.main()
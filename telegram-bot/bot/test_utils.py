@@ -0,0 +1,244 @@
+import random
+import unittest
+from datetime import datetime, timedelta, timezone
+
+import fakes
+from utils import (
+    apos,
+    generate_seeding,
+    normalize_text,
+    normalize_username,
+    order_submissions,
+    poll_options_error,
+    presentation_order,
+    round_index,
+    round_name,
+    round_starts,
+)
+
+
+class NormalizeUsernameTest(unittest.TestCase):
+    def test_valid(self):
+        self.assertEqual(normalize_username("gif_fan"), "gif_fan")
+        self.assertEqual(normalize_username("GifFan_2000"), "GifFan_2000")
+        self.assertEqual(normalize_username("a" * 32), "a" * 32)
+
+    def test_at_and_whitespace(self):
+        self.assertEqual(normalize_username("@gif_fan"), "gif_fan")
+        self.assertEqual(normalize_username("  @gif_fan\n"), "gif_fan")
+        self.assertIsNone(normalize_username("gif fan"))
+        self.assertIsNone(normalize_username("@@gif_fan"))
+
+    def test_invalid(self):
+        self.assertIsNone(normalize_username(None))
+        self.assertIsNone(normalize_username(""))
+        self.assertIsNone(normalize_username("fan"))
+        self.assertIsNone(normalize_username("a" * 33))
+        self.assertIsNone(normalize_username("gif-fan"))
+        self.assertIsNone(normalize_username("g\u00eff_fan"))
+        self.assertIsNone(normalize_username("gif_fan\U0001F600"))
+
+
+class NormalizeTextTest(unittest.TestCase):
+    def test_collapses_whitespace(self):
+        self.assertEqual(normalize_text("  cat\t\tvideo\n\nfinal.gif ", 255), "cat video final.gif")
+
+    def test_drops_control_characters(self):
+        self.assertEqual(normalize_text("cat\x00\x1b\u200evideo", 255), "catvideo")
+
+    def test_keeps_unicode(self):
+        self.assertEqual(normalize_text("Kesän GIFdome \U0001F525", 64), "Kesän GIFdome \U0001F525")
+        self.assertEqual(normalize_text("\u732b\u306e\u52d5\u753b", 64), "\u732b\u306e\u52d5\u753b")
+
+    def test_truncates(self):
+        self.assertEqual(normalize_text("a" * 10, 10), "a" * 10)
+        self.assertEqual(normalize_text("a" * 11, 10), "a" * 9 + "\u2026")
+        self.assertEqual(normalize_text("\U0001F525" * 11, 10), "\U0001F525" * 9 + "\u2026")
+
+    def test_empty(self):
+        self.assertEqual(normalize_text(" \n\x00 ", 64), "")
+
+
+class SeedingTest(unittest.TestCase):
+    def test_best_seeds_face_worst(self):
+        seeding = generate_seeding(list(range(256)))
+        self.assertEqual(seeding[:6], [0, 255, 1, 254, 2, 253])
+        self.assertEqual(seeding[-2:], [127, 128])
+        self.assertEqual(sorted(seeding), list(range(256)))
+
+    def test_needs_a_full_bracket(self):
+        with self.assertRaises(AssertionError):
+            generate_seeding(list(range(255)))
+
+
+class OrderSubmissionsTest(unittest.TestCase):
+    start = datetime(2026, 1, 1, tzinfo=timezone.utc)
+    rows = [
+        ("late", 5, start + timedelta(hours=3)),
+        ("first", 1, start),
+        ("popular", 9, start + timedelta(hours=2)),
+        ("second", 2, start + timedelta(hours=1)),
+    ]
+
+    def test_recency(self):
+        self.assertEqual(
+            order_submissions(self.rows, "recency", random.Random(1)),
+            ["first", "second", "popular", "late"],
+        )
+
+    def test_popularity(self):
+        self.assertEqual(
+            order_submissions(self.rows, "popularity", random.Random(1)),
+            ["popular", "late", "second", "first"],
+        )
+
+    def test_popularity_ties_are_shuffled(self):
+        rows = [(f"gif{i}", 1, self.start) for i in range(20)]
+        orders = {tuple(order_submissions(rows, "popularity", random.Random(seed))) for seed in range(5)}
+        self.assertGreater(len(orders), 1)
+        for order in orders:
+            self.assertEqual(sorted(order), sorted(gif_id for gif_id, _, _ in rows))
+
+    def test_invalid_mode(self):
+        with self.assertRaises(ValueError):
+            order_submissions(self.rows, "manual", random.Random(1))
+
+
+class DuplicateDetectionTest(unittest.TestCase):
+    def load(self, duplicate_detection):
+        gifdome = fakes.load_bot(self, {"duplicate_detection": duplicate_detection}, fakes.taking_submissions())
+        fakes.add_gif(gifdome, "primary")
+        fakes.add_gif(gifdome, "duplicate")
+        fakes.execute(
+            gifdome,
+            'INSERT INTO "duplicates"("duplicate_id", "primary_id") VALUES (%s, %s)',
+            ("duplicate", "primary"),
+        )
+        fakes.add_submission(gifdome, 1, "primary")
+        return gifdome
+
+    def submit_duplicate(self, gifdome):
+        message = fakes.Message(fakes.group_chat(), fakes.user(2), animation=fakes.Animation("duplicate"))
+        return fakes.dispatch(gifdome, gifdome.gif_message, message)
+
+    def submitted_gif(self, gifdome):
+        [(gif_id,)] = fakes.execute(gifdome, 'SELECT "gif_id" FROM "submissions" WHERE "user_id" = 2')
+        return gif_id
+
+    def test_disabled_keeps_duplicates_apart(self):
+        gifdome = self.load(False)
+        self.assertNotIn("This GIF has been blocked.", self.submit_duplicate(gifdome))
+        self.assertEqual(self.submitted_gif(gifdome), "duplicate")
+        self.assertEqual(sorted(gifdome.ordered_submissions("recency")), ["duplicate", "primary"])
+
+    def test_disabled_accepts_duplicate_of_blocked_gif(self):
+        gifdome = self.load(False)
+        fakes.block_gif(gifdome, "primary")
+        self.assertNotIn("This GIF has been blocked.", self.submit_duplicate(gifdome))
+        self.assertEqual(self.submitted_gif(gifdome), "duplicate")
+        self.assertEqual(gifdome.ordered_submissions("recency"), ["duplicate"])
+
+    def test_enabled_merges_duplicates(self):
+        gifdome = self.load(True)
+        self.assertEqual(self.submit_duplicate(gifdome), ["Got it! This GIF has been submitted 2 times."])
+        self.assertEqual(self.submitted_gif(gifdome), "primary")
+        self.assertEqual(gifdome.ordered_submissions("recency"), ["primary"])
+
+    def test_enabled_blocks_duplicate_of_blocked_gif(self):
+        gifdome = self.load(True)
+        fakes.block_gif(gifdome, "primary")
+        self.assertEqual(self.submit_duplicate(gifdome), ["This GIF has been blocked."])
+        self.assertEqual(gifdome.ordered_submissions("recency"), [])
+
+
+class PresentationOrderTest(unittest.TestCase):
+    participants = ["gif_a", "gif_b"]
+
+    def attribute_votes(self, poll_votes, swapped):
+        return dict(zip(self.participants, presentation_order(poll_votes, swapped)))
+
+    def test_swapped_poll(self):
+        self.assertEqual(presentation_order(self.participants, True), ["gif_b", "gif_a"])
+        # The first option of a swapped poll shows gif_b
+        self.assertEqual(self.attribute_votes([7, 3], True), {"gif_a": 3, "gif_b": 7})
+
+    def test_unswapped_poll(self):
+        self.assertEqual(presentation_order(self.participants, False), ["gif_a", "gif_b"])
+        self.assertEqual(self.attribute_votes([7, 3], False), {"gif_a": 7, "gif_b": 3})
+
+
+class RoundIndexTest(unittest.TestCase):
+    def test_round_boundaries(self):
+        for index, start in enumerate(round_starts):
+            with self.subTest(start=start):
+                self.assertEqual(round_index(start), index)
+                if start > 0:
+                    self.assertEqual(round_index(start - 1), index - 1)
+        self.assertEqual(round_index(254), len(round_starts) - 1)
+
+    def test_durations_listed_first_round_first(self):
+        round_durations = [100, 200, 300, 400, 500, 600, 700, 800]
+        matches = fakes.load_bot(self, {"round_durations": round_durations}).generate_matches()
+        for match_num, seconds in [
+            (0, 100),
+            (127, 100),
+            (128, 200),
+            (191, 200),
+            (192, 300),
+            (223, 300),
+            (224, 400),
+            (239, 400),
+            (240, 500),
+            (247, 500),
+            (248, 600),
+            (251, 600),
+            (252, 700),
+            (253, 700),
+            (254, 800),
+        ]:
+            with self.subTest(match_num=match_num):
+                self.assertEqual(matches[match_num]["duration"], seconds)
+
+
+class RoundNameTest(unittest.TestCase):
+    def test_round_boundaries(self):
+        for match_num, name in [
+            (0, "round of 256"),
+            (127, "round of 256"),
+            (128, "round of 128"),
+            (191, "round of 128"),
+            (192, "round of 64"),
+            (223, "round of 64"),
+            (224, "round of 32"),
+            (239, "round of 32"),
+            (240, "round of 16"),
+            (247, "round of 16"),
+            (248, "quarterfinals"),
+            (251, "quarterfinals"),
+            (252, "semifinals"),
+            (253, "semifinals"),
+            (254, "the FINALE"),
+        ]:
+            with self.subTest(match_num=match_num):
+                self.assertEqual(round_name(match_num), name)
+
+    def test_out_of_range(self):
+        self.assertEqual(round_name(-1), f"wait, that shouldn{apos}t happen")
+        self.assertEqual(round_name(255), f"wait, that shouldn{apos}t happen")
+
+
+class PollOptionsErrorTest(unittest.TestCase):
+    def test_option_lengths(self):
+        self.assertIsNotNone(poll_options_error(["", "B"]))
+        self.assertIsNone(poll_options_error(["A", "B"]))
+        self.assertIsNone(poll_options_error(["A" * 100, "B" * 100]))
+        self.assertIsNotNone(poll_options_error(["A" * 101, "B"]))
+        self.assertIsNotNone(poll_options_error(["A", "B" * 101]))
+
+    def test_duplicate_options(self):
+        self.assertEqual(poll_options_error(["A", "A"]), "Poll options must differ.")
+        self.assertIsNone(poll_options_error(["A", "a"]))
+
+
+if __name__ == "__main__":
+    unittest.main()
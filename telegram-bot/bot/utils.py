@@ -1,3 +1,5 @@
+import re
+from bisect import bisect_right
 from datetime import datetime, timezone
 
 
@@ -39,15 +41,101 @@ def find_enum_by_value(enum, value):
     return None
 
 
+def generate_seeding(ordered_submissions):
+    assert len(ordered_submissions) == 256
+
+    output = []
+    for i in range(128):
+        output.append(ordered_submissions[i])
+        output.append(ordered_submissions[255 - i])
+    return output
+
+
 def markdown_escape(text):
     return re.sub(r"[\\_*\[\]()~`>#+\-=|{}.!]", r"\\\g<0>", text)
 
 
+def normalize_username(username):
+    # Telegram usernames are 5-32 characters of letters, digits and underscores,
+    # sometimes written with a leading @
+    if username is None:
+        return None
+    username = username.strip().removeprefix("@")
+    if not re.fullmatch(r"[A-Za-z0-9_]{5,32}", username):
+        return None
+    return username
+
+
+def normalize_text(text, max_length):
+    # Drops control characters and collapses whitespace, which would break announcements
+    text = " ".join("".join(char for char in text if char.isprintable() or char.isspace()).split())
+    if len(text) > max_length:
+        text = text[:max_length - 1] + "\u2026"
+    return text
+
+
 def now():
     return int(datetime.now(timezone.utc).timestamp())
 
 
+# Index of the first match of each round, from the round of 256 to the final
+round_starts = [0, 128, 192, 224, 240, 248, 252, 254]
+
+
+def round_index(match_num):
+    return bisect_right(round_starts, match_num) - 1
+
+
+round_names = [
+    "round of 256",
+    "round of 128",
+    "round of 64",
+    "round of 32",
+    "round of 16",
+    "quarterfinals",
+    "semifinals",
+    "the FINALE",
+]
+
+
+def round_name(match_num):
+    # Matches are numbered in bracket order: 128 in the round of 256, 64
+    # in the round of 128 and so on, with the final as match 254
+    if not 0 <= match_num <= 254:
+        return f"wait, that shouldn{apos}t happen"
+    return round_names[round_index(match_num)]
+
+
+def order_submissions(rows, seeding_mode, rng):
+    # rows are (gif_id, submission count, first submission time)
+    rows = list(rows)
+    if seeding_mode == "popularity":
+        # Shuffle first so GIFs with equal submission counts are seeded randomly
+        rng.shuffle(rows)
+        rows.sort(key=lambda row: row[1], reverse=True)
+    elif seeding_mode == "recency":
+        rows.sort(key=lambda row: row[2])
+    else:
+        raise ValueError(f"Invalid seeding mode {seeding_mode!r}")
+    return [gif_id for gif_id, _, _ in rows]
+
+
 def plural(n):
     if n == 1:
         return ""
     return "s"
+
+
+def poll_options_error(options):
+    # Telegram's limit for poll options
+    if not all(1 <= len(option) <= 100 for option in options):
+        return "Poll options must be 1-100 characters."
+    if len(set(options)) != len(options):
+        return "Poll options must differ."
+    return None
+
+
+def presentation_order(items, swapped):
+    # Swapping two items is its own inverse, so this maps bracket order to
+    # poll order and poll order back to bracket order
+    return list(items[::-1]) if swapped else list(items)
@@ -0,0 +1,84 @@
+import os
+import sys
+from pathlib import Path
+
+import psycopg2
+import toml
+from redis import Redis
+from telegram import Bot
+from telegram.error import TelegramError
+
+
+project_path = Path(os.getenv("GIFDOME_DIR", Path(sys.path[0])))
+failures = 0
+
+
+def report(name, ok, detail=""):
+    global failures
+    if not ok:
+        failures += 1
+    print(f"[{'ok' if ok else 'FAIL'}] {name}{f': {detail}' if detail else ''}")
+
+
+try:
+    config = toml.load(project_path / "config.toml")
+    report("config.toml", True)
+except Exception as e:
+    report("config.toml", False, str(e))
+    sys.exit(1)
+
+required = ["admins", "api_token", "debug", "log_file", "max_submissions", "min_votes", "redis_db", "redis_socket"]
+missing = [key for key in required if key not in config]
+report("Required settings", not missing, f"missing {', '.join(missing)}" if missing else "")
+
+update_mode = config.get("update_mode", "webhook")
+report("update_mode", update_mode in ["webhook", "polling"], update_mode)
+if update_mode == "webhook":
+    missing = [key for key in ["webhook_port", "webhook_url"] if key not in config]
+    report("Webhook settings", not missing, f"missing {', '.join(missing)}" if missing else "")
+
+for dir_path in [project_path / "gifs", Path(config.get("log_file", "")).parent]:
+    if not dir_path.is_dir():
+        report(f"Directory {dir_path}", False, "does not exist")
+    elif not os.access(dir_path, os.W_OK):
+        report(f"Directory {dir_path}", False, "not writable")
+    else:
+        report(f"Directory {dir_path}", True)
+
+for filename in ["schema.sql", "versus-template.png"]:
+    report(filename, (project_path / filename).is_file(), "" if (project_path / filename).is_file() else "missing")
+
+try:
+    db = psycopg2.connect(f"dbname={config.get('db_name', 'gifdome')}")
+    with db:
+        with db.cursor() as cur:
+            cur.execute("SELECT 1")
+    db.close()
+    report("PostgreSQL", True)
+except psycopg2.Error as e:
+    report("PostgreSQL", False, str(e).strip())
+
+try:
+    redis = Redis(unix_socket_path=config["redis_socket"], db=config["redis_db"])
+    redis.ping()
+    redis.close()
+    report("Redis", True)
+except Exception as e:
+    report("Redis", False, str(e))
+
+bots = []
+if "api_token" in config:
+    bots.append(("Bot token", Bot(
+        token=config["api_token"],
+        base_url=config.get("api_base_url", "https://api.telegram.org/bot"),
+    )))
+if "admin_api_token" in config:
+    bots.append(("Admin bot token", Bot(token=config["admin_api_token"])))
+for name, bot in bots:
+    try:
+        report(name, True, f"@{bot.get_me().username}")
+    except TelegramError as e:
+        report(name, False, e.message)
+
+print("All checks passed" if not failures else f"{failures} check{'s' if failures != 1 else ''} failed")
+sys.exit(1 if failures else 0)
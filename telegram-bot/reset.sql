@@ -1 +1 @@
-DROP TABLE gifs, gif_filenames, submissions, users;
+DROP TABLE banned_users, blocked_gifs, gifs, gif_filenames, submissions, users;
@@ -3,6 +3,10 @@ CREATE TABLE IF NOT EXISTS "users" (
   "username" text NOT NULL
 );
 
+-- Not every Telegram user has a username
+ALTER TABLE "users" ALTER COLUMN "username" DROP NOT NULL;
+ALTER TABLE "users" ADD COLUMN IF NOT EXISTS "show_name" boolean NOT NULL DEFAULT false;
+
 CREATE TABLE IF NOT EXISTS "gifs" (
   "id" text PRIMARY KEY,
   "file_id" text NOT NULL,
@@ -31,3 +35,17 @@ CREATE TABLE IF NOT EXISTS "submissions" (
   "created" timestamp with time zone NOT NULL,
   PRIMARY KEY ("user_id", "gif_id")
 );
+
+ALTER TABLE "submissions" ADD COLUMN IF NOT EXISTS "description" text;
+-- The GIF as sent, which differs from "gif_id" when it was merged as a duplicate
+ALTER TABLE "submissions" ADD COLUMN IF NOT EXISTS "submitted_gif_id" text;
+
+CREATE TABLE IF NOT EXISTS "blocked_gifs" (
+  "gif_id" text PRIMARY KEY REFERENCES "gifs"("id"),
+  "created" timestamp with time zone NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "banned_users" (
+  "user_id" integer PRIMARY KEY REFERENCES "users"("id"),
+  "created" timestamp with time zone NOT NULL
+);
@@ -0,0 +1,46 @@
+import json
+import os
+import sys
+from pathlib import Path
+
+import psycopg2
+import toml
+from redis import Redis
+
+
+project_path = Path(os.getenv("GIFDOME_DIR", Path(sys.path[0])))
+config = toml.load(project_path / "config.toml")
+
+db = psycopg2.connect(f"dbname={config.get('db_name', 'gifdome')}")
+redis = Redis(unix_socket_path=config["redis_socket"], db=config["redis_db"])
+
+
+def redis_get_int(key):
+    try:
+        return int(redis.get(key))
+    except (TypeError, ValueError):
+        return None
+
+
+state = redis.get("state")
+print(f"State: {state.decode() if state is not None else 'not-started'}")
+
+if (group_id := redis_get_int("group_id")) is not None:
+    print(f"Group: {group_id}")
+
+with db:
+    with db.cursor() as cur:
+        cur.execute('SELECT count(DISTINCT "gif_id"), count(DISTINCT "user_id") FROM "submissions"')
+        gif_count, user_count = cur.fetchone()
+print(f"Submissions: {gif_count} GIFs from {user_count} users")
+
+if (match_index := redis_get_int("current_match")) is not None:
+    matches = json.loads(redis.get("matches"))
+    match = matches[match_index]
+    voter_count = redis_get_int("current_voter_count") or 0
+    print(f"Current match: {match_index + 1}/{len(matches)}, {voter_count} voters")
+    if (poll_start := redis_get_int("current_poll_start")) is not None:
+        print(f"Poll started at {poll_start}, duration {match['duration']} seconds")
+
+db.close()
+redis.close()
@@ -1,13 +1,3 @@
-def generate_seeding(ordered_submissions):
-    assert len(ordered_submissions) == 256
-
-    output = []
-    for i in range(128):
-        output.append(ordered_submissions[i])
-        output.append(ordered_submissions[255 - i])
-    return output
-
-
 def bracket_coordinates():
     coords = {}
     for i in range(128, 160):
@@ -1,6 +1,6 @@
 import json
 
-from flask import Blueprint, jsonify
+from flask import Blueprint, abort, jsonify
 
 from . import db, redis
 
@@ -16,6 +16,12 @@ def matches():
     return jsonify(json.loads(raw))
 
 
+def match_participants(index, matches, seeding):
+    if index < 128:
+        return [seeding[index * 2], seeding[index * 2 + 1]]
+    return [match["winner"] for match in matches if match["next"] == index]
+
+
 @api_v1.get("/gifs.json")
 def gifs():
     with db:
@@ -50,3 +56,90 @@ def submissions():
                 'SELECT "gif_id", count(*) FROM "submissions" GROUP BY "gif_id"'
             )
             return {id_: count for id_, count in cur}
+
+
+@api_v1.get("/gifs/<gif_id>.json")
+def gif(gif_id):
+    with db:
+        with db.cursor() as cur:
+            cur.execute(
+                """
+                SELECT "file_id", "file_size", "mime_type", "width", "height", "duration"
+                FROM "gifs" WHERE "id" = %s
+                """,
+                (gif_id,),
+            )
+            if cur.rowcount != 1:
+                abort(404)
+            file_id, file_size, mime_type, width, height, duration = cur.fetchone()
+            data = {
+                "id": gif_id,
+                "file": file_id,
+                "file_size": file_size,
+                "mime_type": mime_type,
+                "width": width,
+                "height": height,
+                "duration": duration,
+            }
+            cur.execute('SELECT "filename" FROM "gif_filenames" WHERE "gif_id" = %s', (gif_id,))
+            data["filenames"] = [filename for filename, in cur]
+            cur.execute('SELECT count(*) FROM "submissions" WHERE "gif_id" = %s', (gif_id,))
+            data["submissions"], = cur.fetchone()
+
+    wins = losses = 0
+    raw_matches = redis.get("matches")
+    raw_seeding = redis.get("seeding")
+    if raw_matches is not None and raw_seeding is not None:
+        matches = json.loads(raw_matches)
+        seeding = json.loads(raw_seeding)
+        for i, match in enumerate(matches):
+            if match["winner"] is None:
+                continue
+            if match["winner"] == gif_id:
+                wins += 1
+            elif gif_id in match_participants(i, matches, seeding):
+                losses += 1
+    data["wins"] = wins
+    data["losses"] = losses
+    return data
+
+
+@api_v1.get("/stats.json")
+def stats():
+    with db:
+        with db.cursor() as cur:
+            cur.execute('SELECT count(*) FROM "gifs"')
+            gif_count, = cur.fetchone()
+            cur.execute('SELECT count(*), count(DISTINCT "gif_id"), count(DISTINCT "user_id") FROM "submissions"')
+            submission_count, submitted_gif_count, user_count = cur.fetchone()
+            cur.execute('SELECT count(DISTINCT "primary_id") FROM "duplicates"')
+            duplicate_group_count, = cur.fetchone()
+            cur.execute('SELECT count(*) FROM "blocked_gifs"')
+            blocked_count, = cur.fetchone()
+            cur.execute(
+                """
+                SELECT "gif_id", count(*) FROM "submissions"
+                GROUP BY "gif_id" ORDER BY count(*) DESC, min("created") LIMIT 1
+                """)
+            most_submitted = cur.fetchone()
+
+    state = redis.get("state")
+    title = redis.get("title")
+    raw_matches = redis.get("matches")
+    matches = json.loads(raw_matches) if raw_matches is not None else []
+    return {
+        "state": state.decode() if state is not None else None,
+        "title": title.decode() if title is not None else None,
+        "gifs": gif_count,
+        "submissions": submission_count,
+        "submitted_gifs": submitted_gif_count,
+        "submitters": user_count,
+        "duplicate_groups": duplicate_group_count,
+        "blocked_gifs": blocked_count,
+        "most_submitted": (
+            {"id": most_submitted[0], "submissions": most_submitted[1]}
+            if most_submitted is not None else None
+        ),
+        "matches_played": sum(match["winner"] is not None for match in matches),
+        "matches_total": len(matches),
+    }